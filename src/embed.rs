@@ -0,0 +1,301 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use crate::{
+    errors::{Diagnostic, RuntimeError, RuntimeResult},
+    interpreter::Interpreter,
+    native_class::NativeClass,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+    syntax::stmt::Stmt,
+    tokens::{
+        token::{ref_cell, LoxCallable, LoxCallableType, LoxType, Token},
+        token_type::TokenType,
+    },
+    vm::{Compiler, Vm},
+};
+
+#[derive(Debug)]
+pub enum LoxError {
+    /// The source could not be scanned, parsed or resolved. Nothing was run.
+    Compile(Vec<Diagnostic>),
+    /// An error escaped to the top level while running the source.
+    Runtime(Diagnostic),
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxError::Compile(errs) => {
+                let lines = errs.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", lines.join("\n"))
+            }
+            LoxError::Runtime(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+pub type NativeFn = dyn Fn(Vec<LoxType>) -> Result<LoxType, String>;
+
+/// A Rust closure exposed to scripts as a regular function.
+///
+/// Returning `Err(message)` raises a runtime error in the calling script.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    fun: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<T, F>(name: T, arity: usize, fun: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(Vec<LoxType>) -> Result<LoxType, String> + 'static,
+    {
+        Self {
+            name: name.into(),
+            arity,
+            fun: Box::new(fun),
+        }
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn kind(&self) -> LoxCallableType {
+        LoxCallableType::NativeFunction
+    }
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn arity(&self) -> usize {
+        self.arity
+    }
+    fn call(&self, _: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+        (self.fun)(args).map_err(|message| RuntimeError::new(message, 0))
+    }
+}
+
+impl From<NativeFunction> for LoxType {
+    fn from(value: NativeFunction) -> Self {
+        LoxType::Callable(ref_cell(value))
+    }
+}
+
+/// Scan, parse and resolve a program, tagging its spans with `source_id`.
+pub(crate) fn parse_program(source: String, source_id: u32) -> Result<Vec<Stmt>, Vec<Diagnostic>> {
+    let tokens = Scanner::new(source)
+        .with_source_id(source_id)
+        .scan_tokens()
+        .map_err(|e| vec![e.into()])?;
+
+    // Resolve whatever parsed cleanly so that parse and resolve errors
+    // are reported together.
+    let mut errs = Vec::new();
+    let mut stmts = Parser::new(tokens)
+        .parse()
+        .into_iter()
+        .filter_map(|stmt| stmt.map_err(|e| errs.push(Diagnostic::from(e))).ok())
+        .collect::<Vec<_>>();
+    if let Err(resolve_errs) = Resolver::new().resolve_statements(&mut stmts) {
+        errs.extend(resolve_errs.into_iter().map(Diagnostic::from));
+    }
+    if !errs.is_empty() {
+        errs.sort_by_key(|e| (e.line, e.span.start));
+        return Err(errs);
+    }
+    Ok(stmts)
+}
+
+/// Which runtime executes resolved programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Walk the syntax tree directly.
+    #[default]
+    TreeWalker,
+    /// Compile to bytecode and run it on the stack VM.
+    Vm,
+}
+
+enum Runtime {
+    TreeWalker(Interpreter),
+    Vm(Vm),
+}
+
+/// An embeddable Lox interpreter.
+///
+/// State persists between calls to [`Lox::eval`], so globals declared by one
+/// snippet are visible to the next one, the same way the REPL behaves.
+pub struct Lox {
+    runtime: Runtime,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self::with_backend(Backend::TreeWalker)
+    }
+
+    pub fn with_backend(backend: Backend) -> Self {
+        let interpreter = Interpreter::new();
+        let runtime = match backend {
+            Backend::TreeWalker => Runtime::TreeWalker(interpreter),
+            Backend::Vm => Runtime::Vm(Vm::new(interpreter)),
+        };
+        Self { runtime }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.runtime {
+            Runtime::TreeWalker(_) => Backend::TreeWalker,
+            Runtime::Vm(_) => Backend::Vm,
+        }
+    }
+
+    fn interpreter(&self) -> &Interpreter {
+        match &self.runtime {
+            Runtime::TreeWalker(i) => i,
+            Runtime::Vm(vm) => &vm.interpreter,
+        }
+    }
+
+    fn interpreter_mut(&mut self) -> &mut Interpreter {
+        match &mut self.runtime {
+            Runtime::TreeWalker(i) => i,
+            Runtime::Vm(vm) => &mut vm.interpreter,
+        }
+    }
+
+    /// Send what scripts `print`, or write with `io.write` and `io.printf`,
+    /// to `writer` instead of standard output. A
+    /// [`SharedBuffer`](crate::SharedBuffer) captures it in memory.
+    pub fn set_output<W: Write + 'static>(&mut self, writer: W) {
+        self.interpreter_mut().io.stdout = Box::new(writer);
+    }
+
+    /// Send what scripts write with `io.eprint` to `writer` instead of
+    /// standard error.
+    pub fn set_error_output<W: Write + 'static>(&mut self, writer: W) {
+        self.interpreter_mut().io.stderr = Box::new(writer);
+    }
+
+    /// Make `io.input`, `io.readline` and `io.read_all` read from `reader`
+    /// instead of standard input.
+    pub fn set_input<R: BufRead + 'static>(&mut self, reader: R) {
+        self.interpreter_mut().io.set_stdin(reader);
+    }
+
+    /// Treat `nil` as true in conditions, as older versions of the
+    /// interpreter did, instead of as false like the rest of the Lox family.
+    pub fn set_nil_is_truthy(&mut self, nil_is_truthy: bool) {
+        match &mut self.runtime {
+            Runtime::TreeWalker(i) => i.nil_is_truthy = nil_is_truthy,
+            Runtime::Vm(vm) => vm.interpreter.nil_is_truthy = nil_is_truthy,
+        }
+    }
+
+    /// Tell scripts how many bytes of native stack [`eval`](Self::eval) is
+    /// called with, so that deep recursion fails with "Stack overflow."
+    /// instead of crashing the process. The default,
+    /// [`DEFAULT_STACK_SIZE`](crate::interpreter::DEFAULT_STACK_SIZE), is
+    /// the 2 MiB Rust gives a spawned thread; a larger stack allows deeper
+    /// recursion.
+    pub fn set_stack_size(&mut self, bytes: usize) {
+        self.interpreter_mut().stack_size = bytes;
+    }
+
+    /// Resolve imports of the main program relative to the directory of
+    /// `path`, the file its source was read from, and report importing it
+    /// back as a cycle.
+    pub fn set_script_path<P: AsRef<Path>>(&mut self, path: P) {
+        match &mut self.runtime {
+            Runtime::TreeWalker(i) => i.modules.set_script(path.as_ref()),
+            Runtime::Vm(vm) => vm.interpreter.modules.set_script(path.as_ref()),
+        }
+    }
+
+    /// Scan, parse, resolve and run `source`, returning the value of the last
+    /// statement.
+    ///
+    /// With the VM backend, a function, class or instance declared by the
+    /// script comes back as an opaque [`LoxType::Vm`] handle, which can be
+    /// printed, compared and passed back to the same `Lox`.
+    pub fn eval<T: Into<String>>(&mut self, source: T) -> Result<LoxType, LoxError> {
+        let source = source.into();
+        if source.is_empty() {
+            return Ok(Default::default());
+        }
+        let stmts = parse_program(source, 0).map_err(LoxError::Compile)?;
+        self.interpreter_mut().mark_stack_base();
+
+        let result = match &mut self.runtime {
+            Runtime::TreeWalker(interpreter) => {
+                interpreter.interpret(&stmts).map_err(Diagnostic::from)
+            }
+            Runtime::Vm(vm) => {
+                let (function, has_value) = Compiler::new()
+                    .compile(&stmts)
+                    .map_err(|e| LoxError::Compile(vec![e.into()]))?;
+                match vm.interpret(function) {
+                    Ok(val) if has_value => Ok(val.to_lox()),
+                    Ok(_) => Ok(Default::default()),
+                    Err(e) => Err(e.into()),
+                }
+            }
+        };
+        result.map_err(|mut err| {
+            err.file = self
+                .interpreter()
+                .modules
+                .source_path(err.span.source)
+                .map(|path| path.display().to_string());
+            LoxError::Runtime(err)
+        })
+    }
+
+    /// Read a global variable, or `None` if it was never defined.
+    ///
+    /// With the VM backend, functions, classes and instances declared by the
+    /// script are returned as [`LoxType::Vm`] handles, as with
+    /// [`eval`](Self::eval).
+    pub fn get_global(&self, name: &str) -> Option<LoxType> {
+        match &self.runtime {
+            Runtime::TreeWalker(_) => {
+                let name = Token::dummy(name, TokenType::Identifier);
+                self.interpreter().globals.borrow().get(&name).ok()
+            }
+            Runtime::Vm(vm) => vm.get_global(name).map(|v| v.to_lox()),
+        }
+    }
+
+    /// Define or overwrite a global variable. Modules imported afterwards see
+    /// it as well.
+    pub fn set_global<T: Into<LoxType>>(&mut self, name: &str, value: T) {
+        let value = value.into();
+        self.interpreter_mut().builtins.define(name, value.clone());
+        match &mut self.runtime {
+            Runtime::TreeWalker(interpreter) => {
+                interpreter.globals.borrow_mut().define(name, value)
+            }
+            Runtime::Vm(vm) => vm.set_global(name, value.into()),
+        }
+    }
+
+    /// Expose a Rust closure to scripts as the global function `name`.
+    pub fn register_fn<F>(&mut self, name: &str, arity: usize, fun: F)
+    where
+        F: Fn(Vec<LoxType>) -> Result<LoxType, String> + 'static,
+    {
+        self.set_global(name, NativeFunction::new(name, arity, fun));
+    }
+
+    /// Expose a class implemented in Rust to scripts, under its own name.
+    pub fn register_class(&mut self, class: NativeClass) {
+        let class = class.build();
+        self.set_global(&class.name(), class);
+    }
+}
@@ -2,25 +2,17 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     errors::{EnvError, EnvResult},
+    gc::{Edge, Trace},
     tokens::token::{LoxType, Token},
 };
 
 pub type EnclosingEnv = Rc<RefCell<Environment>>;
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Environment {
     values: HashMap<String, LoxType>,
     pub enclosing: Option<EnclosingEnv>,
 }
 
-impl Default for Environment {
-    fn default() -> Self {
-        Self {
-            values: HashMap::new(),
-            enclosing: None,
-        }
-    }
-}
-
 impl Environment {
     #[inline]
     pub fn new(enclosing: Option<EnclosingEnv>) -> Self {
@@ -44,9 +36,9 @@ impl Environment {
                     outer.borrow_mut().assign(name, value)?;
                     Ok(())
                 }
-                None => Err(EnvError::new(
+                None => Err(EnvError::at(
                     format!("Undefined variable '{}'", name.lexeme),
-                    name.line,
+                    name.span,
                 )),
             }
         }
@@ -56,14 +48,13 @@ impl Environment {
             self.values
                 .get(&name.lexeme)
                 .ok_or_else(|| {
-                    EnvError::new(format!("Undefined variable '{}'", name.lexeme), name.line)
+                    EnvError::at(format!("Undefined variable '{}'", name.lexeme), name.span)
                 })
-                .map(|f| f.clone())
+                .cloned()
         } else {
             let enc = self.enclosing.as_ref().unwrap();
             let b = enc.borrow();
-            let res = b.get_at(name, dist - 1);
-            res
+            b.get_at(name, dist - 1)
         }
     }
 
@@ -74,8 +65,7 @@ impl Environment {
         } else {
             let enc = self.enclosing.as_ref().unwrap();
             let mut b = enc.borrow_mut();
-            let res = b.assign_at(name, value, dist - 1);
-            res
+            b.assign_at(name, value, dist - 1)
         }
     }
 
@@ -84,17 +74,31 @@ impl Environment {
             self.values
                 .get(&name.lexeme)
                 .ok_or_else(|| {
-                    EnvError::new(format!("Undefined variable '{}'", name.lexeme), name.line)
+                    EnvError::at(format!("Undefined variable '{}'", name.lexeme), name.span)
                 })
-                .map(|f| f.clone())
+                .cloned()
         } else {
             match &self.enclosing {
                 Some(outer) => outer.borrow().get(name),
-                None => Err(EnvError::new(
+                None => Err(EnvError::at(
                     format!("Undefined variable '{}'", name.lexeme),
-                    name.line,
+                    name.span,
                 )),
             }
         }
     }
 }
+
+impl Trace for Environment {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        self.values
+            .values()
+            .for_each(|value| visit(Edge::Value(value)));
+        if let Some(enclosing) = &self.enclosing {
+            visit(Edge::Env(enclosing));
+        }
+    }
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+}
@@ -1,66 +1,48 @@
-use crate::{interpreter::Interpreter, resolver::Resolver, tokens::token::LoxType};
+use std::fmt::Display;
 
-#[derive(Debug)]
+use crate::tokens::token::{LoxType, Span};
+
+#[derive(Debug, Clone)]
 pub enum InterruptKind {
     Builtin,
     Return(LoxType),
+    Break,
+    Continue,
+    /// A value thrown by a `throw` statement.
+    Throw(LoxType),
 }
 
-#[derive(Debug)]
-pub struct ResolverError {
-    pub message: String,
+/// One entry of a call stack: the function that was running and the line it
+/// was on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: String,
     pub line: usize,
-    pub interrupt_kind: InterruptKind,
-    pub interpreter: Interpreter,
 }
-impl ResolverError {
-    pub fn new<T: Into<String>>(message: T, line: usize, i: Interpreter) -> Self {
+
+impl StackFrame {
+    pub fn new<T: Into<String>>(function: T, line: usize) -> Self {
         Self {
-            message: message.into(),
+            function: function.into(),
             line,
-            interrupt_kind: InterruptKind::Builtin,
-            interpreter: i,
         }
     }
 }
-pub type ResolverResult<T> = Result<(T, Resolver), ResolverError>;
 
-impl RuntimeError {
-    pub fn as_return(value: LoxType, this: Interpreter) -> Self {
-        Self {
-            message: Default::default(),
-            line: 0,
-            interrupt_kind: InterruptKind::Return(value),
-            interpreter: this,
-        }
-    }
-}
-#[derive(Debug)]
-pub struct RuntimeError {
-    pub message: String,
-    pub line: usize,
-    pub interrupt_kind: InterruptKind,
-    pub interpreter: Interpreter,
-}
-impl RuntimeError {
-    pub fn new<T: Into<String>>(message: T, line: usize, i: Interpreter) -> Self {
-        Self {
-            message: message.into(),
-            line,
-            interrupt_kind: InterruptKind::Builtin,
-            interpreter: i,
-        }
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at {}() line {}", self.function, self.line)
     }
 }
 
-pub type RuntimeResult<T> = Result<T, RuntimeError>;
-
 macro_rules! err_struct {
     ($name:ident,$err:ident) => {
-        #[derive(Debug)]
+        #[derive(Debug, Clone)]
         pub struct $name {
             pub message: String,
             pub line: usize,
+            pub span: Span,
+            pub trace: Vec<StackFrame>,
             pub interrupt_kind: InterruptKind,
         }
         impl $name {
@@ -68,9 +50,31 @@ macro_rules! err_struct {
                 Self {
                     message: message.into(),
                     line,
+                    span: Default::default(),
+                    trace: Vec::new(),
                     interrupt_kind: InterruptKind::Builtin,
                 }
             }
+            pub fn at<T: Into<String>>(message: T, span: Span) -> Self {
+                Self {
+                    message: message.into(),
+                    line: span.line,
+                    span,
+                    trace: Vec::new(),
+                    interrupt_kind: InterruptKind::Builtin,
+                }
+            }
+        }
+        impl From<$name> for Diagnostic {
+            fn from(value: $name) -> Self {
+                Self {
+                    message: value.message,
+                    line: value.line,
+                    span: value.span,
+                    trace: value.trace,
+                    file: None,
+                }
+            }
         }
         pub type $err<T> = Result<T, $name>;
     };
@@ -79,5 +83,149 @@ macro_rules! err_struct {
 err_struct!(ScanError, ScanResult);
 
 err_struct!(ParseError, ParseResult);
+err_struct!(ResolverError, ResolverResult);
+err_struct!(RuntimeError, RuntimeResult);
 err_struct!(EnvError, EnvResult);
 err_struct!(LoxClassError, LoxClassResult);
+err_struct!(CompileError, CompileResult);
+err_struct!(VmError, VmResult);
+
+impl RuntimeError {
+    pub fn as_return(value: LoxType) -> Self {
+        Self::interrupt(InterruptKind::Return(value))
+    }
+
+    /// An error that is not an error at all, but unwinds the interpreter up
+    /// to the function or loop handling `kind`.
+    pub fn interrupt(kind: InterruptKind) -> Self {
+        Self {
+            message: Default::default(),
+            line: 0,
+            span: Default::default(),
+            trace: Vec::new(),
+            interrupt_kind: kind,
+        }
+    }
+
+    /// Attach `span` if the error was raised somewhere that did not know its
+    /// source position, such as a native function.
+    pub fn or_at(mut self, span: Span) -> Self {
+        let builtin = matches!(
+            self.interrupt_kind,
+            InterruptKind::Builtin | InterruptKind::Throw(_)
+        );
+        if builtin && !self.span.is_known() && self.line == 0 {
+            self.span = span;
+            self.line = span.line;
+        }
+        self
+    }
+}
+
+impl From<EnvError> for RuntimeError {
+    fn from(value: EnvError) -> Self {
+        Self::at(value.message, value.span)
+    }
+}
+
+impl From<LoxClassError> for RuntimeError {
+    fn from(value: LoxClassError) -> Self {
+        Self::at(value.message, value.span)
+    }
+}
+
+/// An error message tied to a position in the source, ready to be shown to
+/// the user.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+    /// Call stack of a runtime error, innermost call first.
+    pub trace: Vec<StackFrame>,
+    /// The imported module the error points into, if it is not in the main
+    /// program.
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(message: T, line: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            span: Default::default(),
+            trace: Vec::new(),
+            file: None,
+        }
+    }
+
+    fn header(&self) -> String {
+        match &self.file {
+            Some(file) => format!("[{file} line {}] Error: {}", self.line, self.message),
+            None => format!("[line {}] Error: {}", self.line, self.message),
+        }
+    }
+
+    /// The call stack, one frame per line. Runs of identical frames, as left
+    /// behind by runaway recursion, are folded into a single note.
+    fn traceback(&self) -> String {
+        let mut out = String::new();
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            let mut repeated = 0;
+            while frames.peek() == Some(&frame) {
+                frames.next();
+                repeated += 1;
+            }
+            out.push_str(&format!("\n  {frame}"));
+            if repeated > 0 {
+                out.push_str(&format!(
+                    "\n  [previous frame repeated {repeated} more times]"
+                ));
+            }
+        }
+        out
+    }
+
+    /// Render the diagnostic together with the offending line of `source`
+    /// and a caret underline, e.g.
+    ///
+    /// ```text
+    /// [line 3] Error: Undefined variable 'b'
+    ///   |
+    /// 3 | print a + b;
+    ///   |           ^
+    /// ```
+    ///
+    /// followed by the call stack for runtime errors raised inside functions.
+    pub fn render(&self, source: &str) -> String {
+        let header = self.header();
+        let traceback = self.traceback();
+        if !self.span.is_known() || self.file.is_some() {
+            return format!("{header}{traceback}");
+        }
+        let Some(text) = source.lines().nth(self.span.line - 1) else {
+            return format!("{header}{traceback}");
+        };
+        let line_no = self.span.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let pad = (self.span.column as usize).saturating_sub(1);
+        let width = source
+            .get(self.span.start..self.span.end)
+            .map(|s| s.lines().next().unwrap_or("").chars().count())
+            .unwrap_or(0)
+            .max(1)
+            .min(text.chars().count().saturating_sub(pad).max(1));
+        format!(
+            "{header}\n{gutter} |\n{line_no} | {text}\n{gutter} | {}{}{traceback}",
+            " ".repeat(pad),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.header(), self.traceback())
+    }
+}
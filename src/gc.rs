@@ -0,0 +1,299 @@
+//! Cycle collector for the reference counted objects of the tree-walker.
+//!
+//! Environments, instances, functions, classes, lists and maps are all
+//! `Rc<RefCell<...>>`, so reference cycles between them are never freed by
+//! reference counting alone. Every such object is registered here when it is
+//! allocated, and [`collect`] finds the ones only kept alive by cycles.
+//!
+//! The collector does not need an explicit root set. It counts the references
+//! each object receives from other tracked objects; any strong reference
+//! beyond those is held from outside the heap, by the interpreter's `env` and
+//! `globals`, its call stack or a Rust local, and makes the object a root.
+//! Everything not reachable from a root is garbage and gets cleared, which
+//! breaks its cycles and lets reference counting free it.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    environment::EnclosingEnv,
+    lox_function::LoxFunction,
+    tokens::token::{ref_cell, LoxCallable, LoxType},
+};
+
+/// Number of allocations between automatic collections while the heap is
+/// small. The interval grows with the number of live objects.
+pub const DEFAULT_THRESHOLD: usize = 10_000;
+
+/// A strong reference held by a collectable object.
+pub enum Edge<'a> {
+    Value(&'a LoxType),
+    Env(&'a EnclosingEnv),
+    Callable(&'a Rc<RefCell<dyn LoxCallable>>),
+    Method(&'a Rc<RefCell<LoxFunction>>),
+}
+
+impl Edge<'_> {
+    fn key(&self) -> Option<*const ()> {
+        let key = match self {
+            Edge::Value(LoxType::Data(rc)) => Rc::as_ptr(rc) as *const (),
+            Edge::Value(LoxType::Callable(rc)) => Rc::as_ptr(rc) as *const (),
+            Edge::Value(LoxType::List(rc)) => Rc::as_ptr(rc) as *const (),
+            Edge::Value(LoxType::Map(rc)) => Rc::as_ptr(rc) as *const (),
+            Edge::Value(_) => return None,
+            Edge::Env(rc) => Rc::as_ptr(rc) as *const (),
+            Edge::Callable(rc) => Rc::as_ptr(rc) as *const (),
+            Edge::Method(rc) => Rc::as_ptr(rc) as *const (),
+        };
+        Some(key)
+    }
+}
+
+/// Objects that can hold references to collectable objects.
+pub trait Trace {
+    /// Report every reference the object holds.
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>));
+    /// Drop the references the object holds. Only called on garbage.
+    fn clear(&mut self) {}
+}
+
+trait Collect {
+    /// Trace the object, or return false if it is borrowed mutably.
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) -> bool;
+    fn clear(&self);
+}
+
+impl<T: Trace> Collect for RefCell<T> {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) -> bool {
+        match self.try_borrow() {
+            Ok(object) => {
+                object.trace(visit);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+    fn clear(&self) {
+        if let Ok(mut object) = self.try_borrow_mut() {
+            object.clear();
+        }
+    }
+}
+
+enum Tracked {
+    Object(Weak<dyn Collect>),
+    Callable(Weak<RefCell<dyn LoxCallable>>),
+}
+
+impl Tracked {
+    fn is_alive(&self) -> bool {
+        match self {
+            Tracked::Object(weak) => weak.strong_count() > 0,
+            Tracked::Callable(weak) => weak.strong_count() > 0,
+        }
+    }
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            Tracked::Object(weak) => weak.upgrade().map(Object::Object),
+            Tracked::Callable(weak) => weak.upgrade().map(Object::Callable),
+        }
+    }
+}
+
+enum Object {
+    Object(Rc<dyn Collect>),
+    Callable(Rc<RefCell<dyn LoxCallable>>),
+}
+
+impl Object {
+    fn key(&self) -> *const () {
+        match self {
+            Object::Object(rc) => Rc::as_ptr(rc) as *const (),
+            Object::Callable(rc) => Rc::as_ptr(rc) as *const (),
+        }
+    }
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Object(rc) => Rc::strong_count(rc),
+            Object::Callable(rc) => Rc::strong_count(rc),
+        }
+    }
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) -> bool {
+        match self {
+            Object::Object(rc) => rc.trace(visit),
+            Object::Callable(rc) => match rc.try_borrow() {
+                Ok(callable) => {
+                    callable.trace(visit);
+                    true
+                }
+                Err(_) => false,
+            },
+        }
+    }
+    /// Callables mostly reference environments and classes, so clearing
+    /// those breaks most cycles through them. Classes also drop their
+    /// fields, which scripts can point back at the class.
+    fn clear(&self) {
+        match self {
+            Object::Object(rc) => rc.clear(),
+            Object::Callable(rc) => {
+                if let Ok(callable) = rc.try_borrow() {
+                    callable.clear();
+                }
+            }
+        }
+    }
+    fn downgrade(&self) -> Tracked {
+        match self {
+            Object::Object(rc) => Tracked::Object(Rc::downgrade(rc)),
+            Object::Callable(rc) => Tracked::Callable(Rc::downgrade(rc)),
+        }
+    }
+}
+
+/// Counters describing the work of the collector so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcStats {
+    /// Number of collections run.
+    pub collections: usize,
+    /// Objects freed by the most recent collection.
+    pub last_collected: usize,
+    /// Objects freed by all collections.
+    pub total_collected: usize,
+    /// Objects that survived the most recent collection.
+    pub live: usize,
+}
+
+struct Heap {
+    tracked: Vec<Tracked>,
+    /// Length of `tracked` at which entries for freed objects are dropped.
+    /// A `Weak` keeps the memory of its object allocated, so they must not
+    /// pile up between collections.
+    prune_at: usize,
+    allocations: usize,
+    threshold: usize,
+    stats: GcStats,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        tracked: Vec::new(),
+        prune_at: DEFAULT_THRESHOLD,
+        allocations: 0,
+        threshold: DEFAULT_THRESHOLD,
+        stats: GcStats::default(),
+    });
+}
+
+fn register(tracked: Tracked) {
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.tracked.push(tracked);
+        heap.allocations += 1;
+        if heap.tracked.len() >= heap.prune_at {
+            heap.tracked.retain(Tracked::is_alive);
+            heap.prune_at = DEFAULT_THRESHOLD.max(2 * heap.tracked.len());
+        }
+    });
+}
+
+/// Allocate a collectable object.
+pub fn alloc<T: Trace + 'static>(value: T) -> Rc<RefCell<T>> {
+    let rc = ref_cell(value);
+    let weak: Weak<dyn Collect> = Rc::downgrade(&rc) as Weak<RefCell<T>>;
+    register(Tracked::Object(weak));
+    rc
+}
+
+/// Allocate a callable, tracing it through [`LoxCallable::trace`].
+pub fn alloc_callable<T: LoxCallable + 'static>(value: T) -> LoxType {
+    let rc: Rc<RefCell<dyn LoxCallable>> = ref_cell(value);
+    register(Tracked::Callable(Rc::downgrade(&rc)));
+    LoxType::Callable(rc)
+}
+
+/// Run a collection if enough objects were allocated since the last one.
+pub fn maybe_collect() {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.allocations >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+/// Free every tracked object that is only kept alive by reference cycles,
+/// returning how many objects were found.
+pub fn collect() -> usize {
+    let tracked = HEAP.with(|heap| std::mem::take(&mut heap.borrow_mut().tracked));
+    let mut index = HashMap::with_capacity(tracked.len());
+    let mut objects = Vec::with_capacity(tracked.len());
+    for object in tracked.iter().filter_map(Tracked::upgrade) {
+        if let Entry::Vacant(entry) = index.entry(object.key()) {
+            entry.insert(objects.len());
+            objects.push(object);
+        }
+    }
+    drop(tracked);
+
+    // count the references each object gets from inside the heap
+    let mut internal = vec![0; objects.len()];
+    let mut children = vec![Vec::new(); objects.len()];
+    let mut roots = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        let traced = object.trace(&mut |edge| {
+            if let Some(&child) = edge.key().and_then(|key| index.get(&key)) {
+                internal[child] += 1;
+                children[i].push(child);
+            }
+        });
+        if !traced {
+            roots.push(i);
+        }
+    }
+    // one strong reference is our own upgraded `Rc`
+    roots.extend((0..objects.len()).filter(|&i| objects[i].strong_count() - 1 > internal[i]));
+
+    let mut reachable = vec![false; objects.len()];
+    while let Some(i) = roots.pop() {
+        if !reachable[i] {
+            reachable[i] = true;
+            roots.extend(&children[i]);
+        }
+    }
+
+    let mut survivors = Vec::new();
+    let mut collected = 0;
+    for (object, reachable) in objects.iter().zip(reachable) {
+        if reachable {
+            survivors.push(object.downgrade());
+        } else {
+            object.clear();
+            collected += 1;
+        }
+    }
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let live = survivors.len();
+        heap.tracked.append(&mut survivors);
+        heap.prune_at = DEFAULT_THRESHOLD.max(2 * heap.tracked.len());
+        heap.allocations = 0;
+        heap.threshold = DEFAULT_THRESHOLD.max(live);
+        heap.stats.collections += 1;
+        heap.stats.last_collected = collected;
+        heap.stats.total_collected += collected;
+        heap.stats.live = live;
+    });
+    // dropping the last strong references frees the garbage
+    drop(objects);
+    collected
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| heap.borrow().stats)
+}
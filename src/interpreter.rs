@@ -1,264 +1,484 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cmp::Ordering, collections::HashMap, fs, io::Write, rc::Rc};
 
 use crate::{
+    embed::parse_program,
     environment::{EnclosingEnv, Environment},
-    errors::{RuntimeError, RuntimeResult},
-    globals::initialize_globals,
-    lox_class::LoxClass,
+    errors::{InterruptKind, RuntimeError, RuntimeResult, StackFrame},
+    gc,
+    globals::{error_cls, initialize_globals, set_error_fields},
+    lox_class::{
+        operator_method, set_static, setter_name, static_member, LoxClass, LoxInstance, EQ_METHODS,
+        NEG_METHOD,
+    },
     lox_function::{FunctionKind, LoxFunction},
-    syntax::{expr::Expr, stmt::Stmt},
+    lox_io::IoStreams,
+    lox_list::{self, get_index, set_index, IndexError},
+    lox_map::{self, MapStore},
+    lox_string,
+    module::{load_error, module_name, LoxModule, ModuleLoader},
+    number::{num_op, NumOp},
+    syntax::{
+        expr::Expr,
+        stmt::{MethodKind, Stmt},
+    },
     tokens::{
-        token::{ref_cell, LoxCallableType, LoxInstanceValue, LoxType, Token},
+        token::{LoxCallable, LoxCallableType, LoxInstanceValue, LoxType, Span, Token},
         token_type::TokenType,
     },
+    vm::Object,
 };
 
+/// How deep script functions may recurse before a call fails with a
+/// "Stack overflow." error.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// The size of native stack programs are assumed to run on unless the
+/// embedder says otherwise: what Rust gives a spawned thread.
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
 #[derive(Debug)]
 pub struct Interpreter {
     pub env: EnclosingEnv,
     pub globals: EnclosingEnv,
+    /// The globals every module starts with: the natives defined here plus
+    /// whatever the embedder added through [`crate::Lox`].
+    pub builtins: Environment,
+    /// Script functions currently executing, outermost first, each with the
+    /// line it was called from.
+    pub call_stack: Vec<StackFrame>,
+    /// Class of the values that built-in errors turn into when caught.
+    error_class: LoxClass,
+    pub modules: ModuleLoader,
+    /// Compatibility switch for scripts written against older versions of
+    /// this interpreter, where only `false` was falsy.
+    pub nil_is_truthy: bool,
+    /// Where `print` and the `io` module read and write.
+    pub io: IoStreams,
+    /// Size of the native stack the program runs on. Calls fail with a
+    /// "Stack overflow." error once three quarters of it are used.
+    pub stack_size: usize,
+    /// Address of the native stack where the program started running.
+    stack_base: usize,
+}
+
+/// The current position on the native stack, near enough.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Turn a call stack (outermost first, with call-site lines) into a trace,
+/// innermost first, for code currently running at `line`.
+pub fn stack_trace(call_stack: &[StackFrame], mut line: usize) -> Vec<StackFrame> {
+    let mut trace = Vec::with_capacity(call_stack.len());
+    for frame in call_stack.iter().rev() {
+        trace.push(StackFrame::new(frame.function.clone(), line));
+        line = frame.line;
+    }
+    trace
+}
+
+/// The message of an error for a `throw` nobody catches: the class and
+/// `message` field of an instance that has one, or the thrown value.
+pub fn uncaught_message(value: &LoxType) -> String {
+    match value {
+        LoxType::Data(inst) => {
+            let inst = inst.borrow();
+            match inst.get(&Token::dummy("message", TokenType::Identifier)) {
+                Ok(LoxInstanceValue::Free(msg)) => {
+                    format!("Uncaught {}: {msg}", inst.this.name())
+                }
+                _ => format!("Uncaught {value}"),
+            }
+        }
+        LoxType::Vm(Object::Instance(inst)) => {
+            let inst = inst.borrow();
+            let message = match (inst.fields.get("message"), &inst.native) {
+                (Some(msg), _) => Some(msg.to_string()),
+                (None, Some(native)) => native
+                    .borrow()
+                    .get(&Token::dummy("message", TokenType::Identifier))
+                    .ok()
+                    .and_then(|member| match member {
+                        LoxInstanceValue::Free(msg) => Some(msg.to_string()),
+                        _ => None,
+                    }),
+                (None, None) => None,
+            };
+            match message {
+                Some(msg) => format!("Uncaught {}: {msg}", inst.class.borrow().name),
+                None => format!("Uncaught {value}"),
+            }
+        }
+        value => format!("Uncaught {}", value.repr()),
+    }
+}
+
+/// Point an indexing error at the index, or at the object if it cannot be
+/// indexed at all.
+fn index_error(err: IndexError, object: &Expr, index: &Expr) -> RuntimeError {
+    match err {
+        IndexError::Index(msg) => RuntimeError::at(msg, index.span()),
+        IndexError::NotIndexable => RuntimeError::at(err.message(), object.span()),
+    }
 }
 
 impl Interpreter {
-    fn is_truthy(&self, e: &LoxType) -> bool {
-        !matches!(e, LoxType::False)
+    /// Whether conditions, `and`, `or` and `!` treat `e` as true. Only
+    /// `false` and `nil` are falsy, unless `nil_is_truthy` is set.
+    pub fn is_truthy(&self, e: &LoxType) -> bool {
+        match e {
+            LoxType::False => false,
+            LoxType::Nil => self.nil_is_truthy,
+            _ => true,
+        }
+    }
+
+    /// Count native stack use from here, the start of a program.
+    pub fn mark_stack_base(&mut self) {
+        self.stack_base = stack_address();
+    }
+
+    /// Whether the program has used up its native stack, so that calling
+    /// further would risk overflowing it.
+    pub fn stack_exhausted(&self) -> bool {
+        stack_address().abs_diff(self.stack_base) > self.stack_size - self.stack_size / 4
     }
 
     pub fn stringify(&self, e: &LoxType) -> String {
         e.to_string()
     }
 
-    pub fn interpret(self, statements: Vec<Stmt>) -> RuntimeResult<(LoxType, Self)> {
+    pub fn interpret(&mut self, statements: &[Stmt]) -> RuntimeResult<LoxType> {
         let mut result = Default::default();
-        let mut this = self;
         for stmt in statements {
-            (result, this) = this.execute(stmt)?;
+            result = self.execute(stmt)?;
         }
-        Ok((result, this))
+        Ok(result)
     }
-    fn evaluate(self, expr: Expr) -> RuntimeResult<(LoxType, Self)> {
+    fn evaluate(&mut self, expr: &Expr) -> RuntimeResult<LoxType> {
         match expr {
             Expr::Assign(e) => {
-                let (val, this) = self.evaluate(*e.value)?;
-                let res = if let Some(distance) = e.depth {
-                    this.env
+                let val = self.evaluate(&e.value)?;
+                if let Some(distance) = e.depth {
+                    self.env
                         .borrow_mut()
-                        .assign_at(e.name, val.clone(), distance)
+                        .assign_at(e.name.clone(), val.clone(), distance)?
                 } else {
-                    this.globals.borrow_mut().assign(&e.name, val.clone())
+                    self.globals.borrow_mut().assign(&e.name, val.clone())?
                 };
-                match res {
-                    Err(e) => Err(RuntimeError::new(e.message, e.line, this)),
-                    Ok(_) => Ok((val, this)),
-                }
+                Ok(val)
             }
 
             Expr::Binary(e) => {
-                let (left, this) = self.evaluate(*e.left)?;
-                let (right, this) = this.evaluate(*e.right)?;
-
-                fn float_op(a: LoxType, b: LoxType, op: TokenType) -> Result<LoxType, ()> {
-                    if let LoxType::Float(a) = a {
-                        if let LoxType::Float(b) = b {
-                            let lv: LoxType = match op {
-                                TokenType::Minus => (a - b).into(),
-                                TokenType::Slash => (a / b).into(),
-                                TokenType::Star => (a * b).into(),
-                                TokenType::Plus => (a + b).into(),
-                                TokenType::Greater => (a > b).into(),
-                                TokenType::GreaterEqual => (a >= b).into(),
-                                TokenType::Less => (a < b).into(),
-                                TokenType::LessEqual => (a <= b).into(),
-                                TokenType::BangEqual => (a != b).into(),
-                                TokenType::EqualEqual => (a == b).into(),
-                                _ => panic!("Unknown binary op!"),
-                            };
-                            return Ok(lv);
-                        }
+                let left = self.evaluate(&e.left)?;
+                let right = self.evaluate(&e.right)?;
+                if let (LoxType::Data(_), Some(method)) = (&left, operator_method(e.operator.ty)) {
+                    if let Some(res) =
+                        self.overload(&left, method, std::slice::from_ref(&right), e.span)
+                    {
+                        return res;
                     }
-                    return Err(());
                 }
+
                 use TokenType::*;
+                let numbers = left.as_number().zip(right.as_number());
                 match e.operator.ty {
-                    ty @ (Minus | Slash | Star | Greater | GreaterEqual | Less | LessEqual
-                    | BangEqual | EqualEqual) => {
-                        let res = float_op(left, right, ty);
-                        match res {
-                            Ok(t) => Ok((t, this)),
-                            Err(_) => Err({
-                                RuntimeError::new(
-                                    "Invalid operands for binary operation",
-                                    e.operator.line,
-                                    this,
-                                )
-                            }),
+                    ty @ (Greater | GreaterEqual | Less | LessEqual) => match numbers {
+                        Some((a, b)) => {
+                            let ord = a.compare(b);
+                            Ok(match ty {
+                                Greater => ord.is_some_and(Ordering::is_gt),
+                                GreaterEqual => ord.is_some_and(Ordering::is_ge),
+                                Less => ord.is_some_and(Ordering::is_lt),
+                                _ => ord.is_some_and(Ordering::is_le),
+                            }
+                            .into())
                         }
+                        None => Err(RuntimeError::at(
+                            "Invalid operands for binary operation",
+                            e.span,
+                        )),
+                    },
+                    ty @ (Minus | Slash | Star | TildeSlash | Percent | Ampersand | Pipe
+                    | Caret | LessLess | GreaterGreater) => match numbers {
+                        Some((a, b)) => a
+                            .binary(num_op(ty), b)
+                            .map(Into::into)
+                            .map_err(|msg| RuntimeError::at(msg, e.span)),
+                        None => Err(RuntimeError::at(
+                            "Invalid operands for binary operation",
+                            e.span,
+                        )),
+                    },
+                    EqualEqual => self.equals(left, right, e.span),
+                    BangEqual => {
+                        let equal = self.equals(left, right, e.span)?;
+                        Ok((!self.is_truthy(&equal)).into())
                     }
 
                     TokenType::Plus => match (left, right) {
                         (LoxType::String(mut left_str), LoxType::String(right_str)) => {
                             left_str.push_str(right_str.as_str());
-                            Ok((LoxType::String(left_str), this))
+                            Ok(LoxType::String(left_str))
+                        }
+                        (a, b) if numbers.is_some() => {
+                            let (a, b) = (a.as_number().unwrap(), b.as_number().unwrap());
+                            a.binary(NumOp::Add, b)
+                                .map(Into::into)
+                                .map_err(|msg| RuntimeError::at(msg, e.span))
                         }
-                        (LoxType::Float(l), LoxType::Float(r)) => Ok(((l + r).into(), this)),
 
                         (a, b) => {
                             let msg=format!(
                             "Invalid addition. Operands must be 2 strings or 2 numbers. Found: {a}, {b}"
                         );
-                            Err(RuntimeError::new(msg, e.operator.line, this))
+                            Err(RuntimeError::at(msg, e.span))
                         }
                     },
                     _ => panic!("Unknown binary op!"),
                 }
             }
             Expr::Call(e) => {
-                let (callee, mut this) = self.evaluate(*e.callee)?;
+                let callee = self.evaluate(&e.callee)?;
                 let mut args = Vec::with_capacity(e.args.len());
-                for arg in e.args {
-                    let it;
-                    (it, this) = this.evaluate(arg)?;
-                    args.push(it);
+                for arg in &e.args {
+                    args.push(self.evaluate(arg)?);
                 }
 
                 match callee {
-                    LoxType::Callable(f) => {
-                        let mut f = f.borrow_mut();
-                        if args.len() != f.arity() {
-                            return Err(RuntimeError::new(
-                                format!("Expected {} args, got {}", f.arity(), args.len()),
-                                e.paren.line,
-                                this,
-                            ));
-                        }
-                        let (res, this) = f.call(this, args)?;
-                        Ok((res, this))
-                    }
-                    _ => {
-                        return Err(RuntimeError::new(
-                            "Cannot call uncallable",
-                            e.paren.line,
-                            this,
-                        ))
-                    }
+                    LoxType::Callable(f) => self.call(&*f.borrow(), args, e.span),
+                    _ => return Err(RuntimeError::at("Cannot call uncallable", e.callee.span())),
                 }
             }
-            Expr::Grouping(e) => self.evaluate(*e.expression),
-            Expr::Literal(e) => Ok((e.value, self)),
+            Expr::Grouping(e) => self.evaluate(&e.expression),
+            Expr::Literal(e) => Ok(e.value.clone()),
             Expr::Logical(e) => {
-                let (left, this) = self.evaluate(*e.left)?;
+                let left = self.evaluate(&e.left)?;
                 match e.operator.ty {
                     TokenType::Or => {
-                        if this.is_truthy(&left) {
-                            return Ok((left, this));
+                        if self.is_truthy(&left) {
+                            return Ok(left);
                         }
                     }
                     TokenType::And => {
-                        if !this.is_truthy(&left) {
-                            return Ok((left, this));
+                        if !self.is_truthy(&left) {
+                            return Ok(left);
                         }
                     }
                     _ => unreachable!(),
                 }
-                this.evaluate(*e.right)
+                self.evaluate(&e.right)
             }
             Expr::Unary(e) => {
-                let (right, this) = self.evaluate(*e.right)?;
+                let right = self.evaluate(&e.right)?;
+                if e.operator.ty == TokenType::Minus {
+                    if let Some(res) = self.overload(&right, NEG_METHOD, &[], e.span) {
+                        return res;
+                    }
+                }
                 match e.operator.ty {
-                    TokenType::Plus => Err(RuntimeError::new(
-                        "+{value} is not supported",
-                        e.operator.line,
-                        this,
-                    )),
-                    TokenType::Minus => match right {
-                        LoxType::Float(f) => Ok((LoxType::Float(-f), this)),
-                        _ => Err(RuntimeError::new(
+                    TokenType::Plus => Err(RuntimeError::at("+{value} is not supported", e.span)),
+                    TokenType::Minus => match right.as_number() {
+                        Some(n) => n
+                            .negate()
+                            .map(Into::into)
+                            .map_err(|msg| RuntimeError::at(msg, e.span)),
+                        None => Err(RuntimeError::at(
                             "Cannot perform negation on non number",
-                            e.operator.line,
-                            this,
+                            e.span,
+                        )),
+                    },
+                    TokenType::Tilde => match right.as_number() {
+                        Some(n) => n
+                            .bit_not()
+                            .map(Into::into)
+                            .map_err(|msg| RuntimeError::at(msg, e.span)),
+                        None => Err(RuntimeError::at(
+                            "Bitwise operands must be integers.",
+                            e.span,
                         )),
                     },
-                    TokenType::Bang => Ok(((!this.is_truthy(&right)).into(), this)),
+                    TokenType::Bang => Ok((!self.is_truthy(&right)).into()),
                     _ => panic!("?"),
                 }
             }
-            Expr::Variable(e) => {
-                let name = e.name.clone();
-                self.lookup_var(&name, e.into())
-            }
+            Expr::Variable(e) => self.lookup_var(&e.name, e.depth),
             Expr::Get(expr) => {
-                let name = expr.name;
-                let line = name.line;
-                let (obj, this) = self.evaluate(*expr.object)?;
-                // dbg!(&obj);
+                let obj = self.evaluate(&expr.object)?;
                 if let LoxType::Data(inst) = obj {
-                    match inst.borrow().get(name) {
-                        Ok(res) => match res {
-                            LoxInstanceValue::Free(res) => Ok((res, this)),
-                            LoxInstanceValue::Bound(fun) => {
-                                let bound_fun = fun.borrow().bind(LoxType::Data(Rc::clone(&inst)));
-                                Ok((bound_fun.into(), this))
+                    let res = inst.borrow().get(&expr.name)?;
+                    match res {
+                        LoxInstanceValue::Free(res) => Ok(res),
+                        LoxInstanceValue::Bound(fun) => {
+                            let getter = fun.borrow().method_kind() == MethodKind::Getter;
+                            let bound_fun = fun.borrow().bind(LoxType::Data(Rc::clone(&inst)));
+                            if getter {
+                                self.call(&bound_fun, vec![], expr.name.span)
+                            } else {
+                                Ok(bound_fun.into())
                             }
-                        },
-                        Err(e) => Err(RuntimeError::new(e.message, e.line, this)),
+                        }
+                        LoxInstanceValue::Native(method) => {
+                            Ok(method.bind(LoxType::Data(Rc::clone(&inst))).into())
+                        }
                     }
+                } else if let LoxType::List(list) = obj {
+                    lox_list::get_method(&list, &expr.name)
+                } else if let LoxType::Map(map) = obj {
+                    lox_map::get_method(&map, &expr.name)
+                } else if let LoxType::String(s) = obj {
+                    lox_string::get_method(&s, &expr.name)
+                } else if let Some(member) = static_member(&obj, &expr.name.lexeme) {
+                    member.ok_or_else(|| {
+                        RuntimeError::at(
+                            format!("Undefined property '{}'.", expr.name.lexeme),
+                            expr.name.span,
+                        )
+                    })
+                } else if let LoxType::Module(module) = obj {
+                    let value = module.globals.borrow().get(&expr.name);
+                    value.map_err(|_| {
+                        RuntimeError::at(
+                            format!(
+                                "Module '{}' has no member '{}'.",
+                                module.name, expr.name.lexeme
+                            ),
+                            expr.name.span,
+                        )
+                    })
                 } else {
-                    Err(RuntimeError::new(
+                    Err(RuntimeError::at(
                         "Only instances have properties!",
-                        line,
-                        this,
+                        expr.name.span,
                     ))
                 }
             }
             Expr::Set(expr) => {
-                let name = expr.name;
-                let line = name.line;
-                let (obj, this) = self.evaluate(*expr.object)?;
+                let obj = self.evaluate(&expr.object)?;
 
                 if let LoxType::Data(inst) = obj {
-                    let (value, this) = this.evaluate(*expr.value)?;
+                    let value = self.evaluate(&expr.value)?;
 
-                    inst.borrow_mut().set(name, value.clone());
-                    // inst.set(name, value.clone());
-                    Ok((value, this))
+                    let (setter, method) = {
+                        let class = &inst.borrow().this;
+                        (
+                            class.find_method(setter_name(&expr.name.lexeme)),
+                            class.find_method(&expr.name.lexeme),
+                        )
+                    };
+                    if let Some(setter) = setter {
+                        let bound = setter.borrow().bind(LoxType::Data(Rc::clone(&inst)));
+                        self.call(&bound, vec![value.clone()], expr.name.span)?;
+                    } else if method.is_some_and(|m| m.borrow().method_kind() == MethodKind::Getter)
+                    {
+                        return Err(RuntimeError::at(
+                            format!(
+                                "Property '{}' has a getter but no setter.",
+                                expr.name.lexeme
+                            ),
+                            expr.name.span,
+                        ));
+                    } else {
+                        inst.borrow_mut().set(expr.name.clone(), value.clone());
+                    }
+                    Ok(value)
+                } else if matches!(&obj, LoxType::Callable(c) if c.borrow().constructor().is_some())
+                {
+                    let value = self.evaluate(&expr.value)?;
+                    set_static(&obj, &expr.name.lexeme, value.clone());
+                    Ok(value)
                 } else {
-                    Err(RuntimeError::new(
+                    Err(RuntimeError::at(
                         "Only instances have properties!",
-                        line,
-                        this,
+                        expr.name.span,
                     ))
                 }
             }
-            Expr::This(expr) => self.lookup_var(&expr.keyword.clone(), expr.into()),
+            Expr::List(expr) => {
+                let mut elements = Vec::with_capacity(expr.elements.len());
+                for element in &expr.elements {
+                    elements.push(self.evaluate(element)?);
+                }
+                Ok(LoxType::List(gc::alloc(elements)))
+            }
+            Expr::Map(expr) => {
+                let mut map = MapStore::default();
+                for (key, value) in &expr.entries {
+                    let k = self.evaluate(key)?;
+                    let v = self.evaluate(value)?;
+                    map.insert(k, v)
+                        .map_err(|msg| RuntimeError::at(msg, key.span()))?;
+                }
+                Ok(LoxType::Map(gc::alloc(map)))
+            }
+            Expr::Index(expr) => {
+                let obj = self.evaluate(&expr.object)?;
+                let index = self.evaluate(&expr.index)?;
+                get_index(&obj, &index).map_err(|err| index_error(err, &expr.object, &expr.index))
+            }
+            Expr::Lambda(expr) => {
+                let function = LoxFunction::new(
+                    Rc::clone(&expr.function),
+                    Rc::clone(&self.env),
+                    Rc::clone(&self.globals),
+                    FunctionKind::Function,
+                );
+                Ok(function.into())
+            }
+            Expr::SetIndex(expr) => {
+                let obj = self.evaluate(&expr.object)?;
+                let index = self.evaluate(&expr.index)?;
+                let value = self.evaluate(&expr.value)?;
+                set_index(&obj, index, value.clone())
+                    .map_err(|err| index_error(err, &expr.object, &expr.index))?;
+                Ok(value)
+            }
+            Expr::This(expr) => self.lookup_var(&expr.keyword, expr.depth),
             Expr::Super(expr) => {
                 let dist = expr.depth.expect("Expected depth for super");
-                let x = self.env.borrow().get_at(&expr.keyword, dist);
+                let x = self.env.borrow().get_at(&expr.keyword, dist)?;
                 let method_name = &expr.method.lexeme;
                 match x {
-                    Err(e) => Err(RuntimeError::new(e.message, e.line, self)),
-                    Ok(LoxType::Callable(val)) => {
-                        let cls = val;
-
+                    LoxType::Callable(cls) => {
                         let this_ = self
                             .env
                             .borrow()
                             .get_at(&Token::dummy_this(), dist - 1)
                             .expect("This expected if accessing super!");
-                        match cls
-                            .borrow()
+                        let cls = cls.borrow();
+                        let cls = cls
                             .constructor()
-                            .expect("Expected a valid constructor as a super value.")
-                            .find_method(method_name)
-                        {
-                            Some(meth) => return Ok((meth.borrow().bind(this_).into(), self)),
-                            None => {
-                                return Err(RuntimeError::new(
-                                    format!("Could not find the method {method_name}"),
-                                    expr.method.line,
-                                    self,
-                                ))
-                            }
+                            .expect("Expected a valid constructor as a super value.");
+                        // in a class method `this` is the class itself
+                        let member = match &this_ {
+                            LoxType::Callable(_) => cls.get_static(method_name),
+                            _ => cls
+                                .find_method(method_name)
+                                .map(LoxInstanceValue::Bound)
+                                .or_else(|| {
+                                    cls.find_native_method(method_name)
+                                        .map(LoxInstanceValue::Native)
+                                }),
                         };
+                        match member {
+                            Some(LoxInstanceValue::Free(value)) => Ok(value),
+                            Some(LoxInstanceValue::Native(method)) => Ok(method.bind(this_).into()),
+                            Some(LoxInstanceValue::Bound(meth)) => {
+                                let getter = meth.borrow().method_kind() == MethodKind::Getter;
+                                let bound = meth.borrow().bind(this_);
+                                if getter {
+                                    self.call(&bound, vec![], expr.method.span)
+                                } else {
+                                    Ok(bound.into())
+                                }
+                            }
+                            None => Err(RuntimeError::at(
+                                format!("Could not find the method {method_name}"),
+                                expr.method.span,
+                            )),
+                        }
                     }
                     _ => panic!("Interpreter found an invalid super object!"),
                 }
@@ -266,205 +486,365 @@ impl Interpreter {
         }
     }
 
-    fn lookup_var(self, e: &Token, expr: Expr) -> Result<(LoxType, Interpreter), RuntimeError> {
-        let val = if let Some(distance) = expr.get_depth() {
-            self.env.borrow().get_at(e, distance)
-        } else {
-            self.globals.borrow().get(e)
+    /// Call `f` from code at `span`.
+    fn call(
+        &mut self,
+        f: &dyn LoxCallable,
+        args: Vec<LoxType>,
+        span: Span,
+    ) -> RuntimeResult<LoxType> {
+        if args.len() != f.arity() {
+            return Err(RuntimeError::at(
+                format!("Expected {} args, got {}", f.arity(), args.len()),
+                span,
+            ));
+        }
+        if matches!(f.kind(), LoxCallableType::NativeFunction) {
+            return f.call(self, args).map_err(|err| err.or_at(span));
+        }
+        if self.call_stack.len() >= MAX_CALL_DEPTH || self.stack_exhausted() {
+            return Err(RuntimeError::at("Stack overflow.", span));
+        }
+        gc::maybe_collect();
+        self.call_stack.push(StackFrame::new(f.name(), span.line));
+        let res = f
+            .call(self, args)
+            .map_err(|err| self.traced(err.or_at(span)));
+        self.call_stack.pop();
+        res
+    }
+
+    /// Call the method an instance operand defines for an operator, or
+    /// return `None` if `value` is not an instance or its class has no such
+    /// method.
+    fn overload(
+        &mut self,
+        value: &LoxType,
+        method: &str,
+        args: &[LoxType],
+        span: Span,
+    ) -> Option<RuntimeResult<LoxType>> {
+        let LoxType::Data(inst) = value else {
+            return None;
         };
-        match val {
-            Err(e) => Err(RuntimeError::new(e.message, e.line, self)),
-            Ok(val) => Ok((val, self)),
+        let method = inst.borrow().this.find_method(method)?;
+        let bound = method.borrow().bind(value.clone());
+        Some(self.call(&bound, args.to_vec(), span))
+    }
+
+    /// `left == right`, asking the `__eq__` or `equals` method of an
+    /// instance on the left when its class defines one.
+    fn equals(&mut self, left: LoxType, right: LoxType, span: Span) -> RuntimeResult<LoxType> {
+        if let LoxType::Data(inst) = &left {
+            let method = {
+                let inst = inst.borrow();
+                EQ_METHODS
+                    .iter()
+                    .find_map(|name| inst.this.find_method(*name))
+            };
+            if let Some(method) = method {
+                let bound = method.borrow().bind(left.clone());
+                return self.call(&bound, vec![right], span);
+            }
+        }
+        Ok((left == right).into())
+    }
+
+    /// Record the current call stack on an error escaping from the innermost
+    /// call. Errors that already carry a trace pass through untouched.
+    fn traced(&self, mut err: RuntimeError) -> RuntimeError {
+        if matches!(err.interrupt_kind, InterruptKind::Builtin) && err.trace.is_empty() {
+            err.trace = stack_trace(&self.call_stack, err.line);
         }
+        err
     }
-    fn execute(self, stmt: Stmt) -> RuntimeResult<(LoxType, Self)> {
+
+    /// The value a `catch` clause binds for `err`: the thrown value itself,
+    /// or an `Error` instance describing a built-in error.
+    fn caught_value(&self, err: RuntimeError) -> LoxType {
+        match err.interrupt_kind {
+            InterruptKind::Throw(value) => value,
+            _ => {
+                let trace = if err.trace.is_empty() {
+                    stack_trace(&self.call_stack, err.line)
+                } else {
+                    err.trace
+                };
+                self.error_instance(err.message, err.line, &trace)
+            }
+        }
+    }
+
+    /// An instance of the built-in `Error` class for a runtime error.
+    pub(crate) fn error_instance(
+        &self,
+        message: String,
+        line: usize,
+        trace: &[StackFrame],
+    ) -> LoxType {
+        let mut inst = LoxInstance::new(self.error_class.clone());
+        set_error_fields(&mut inst, message.into(), line, trace);
+        inst.into()
+    }
+
+    fn lookup_var(&self, name: &Token, depth: Option<i32>) -> RuntimeResult<LoxType> {
+        let val = if let Some(distance) = depth {
+            self.env.borrow().get_at(name, distance)?
+        } else {
+            self.globals.borrow().get(name)?
+        };
+        Ok(val)
+    }
+    fn execute(&mut self, stmt: &Stmt) -> RuntimeResult<LoxType> {
         match stmt {
             Stmt::Block(e) => {
                 let new_env = Environment::new(Some(Rc::clone(&self.env)));
-                let this = self.execute_block(e.statements, new_env)?;
-                Ok((LoxType::Nil, this))
+                self.execute_block(&e.statements, new_env)?;
+                Ok(LoxType::Nil)
             }
-            Stmt::Expression(e) => self.evaluate(e.expression),
+            Stmt::Expression(e) => self.evaluate(&e.expression),
             Stmt::Function(e) => {
-                let function =
-                    LoxFunction::new(e.clone(), Rc::clone(&self.env), FunctionKind::Function);
+                let function = LoxFunction::new(
+                    Rc::clone(e),
+                    Rc::clone(&self.env),
+                    Rc::clone(&self.globals),
+                    FunctionKind::Function,
+                );
                 self.env
                     .borrow_mut()
                     .define(&e.name.lexeme, function.into());
-                Ok((Default::default(), self))
+                Ok(Default::default())
             }
             Stmt::If(e) => {
-                let cond = e.cond;
-                let branch = e.then_branch;
-                let else_branch = e.else_branch;
-                let (val, mut this) = self.evaluate(cond)?;
-                if this.is_truthy(&val) {
-                    (_, this) = this.execute(*branch)?;
-                } else if let Some(val) = else_branch {
-                    (_, this) = this.execute(*val)?;
+                let val = self.evaluate(&e.cond)?;
+                if self.is_truthy(&val) {
+                    self.execute(&e.then_branch)?;
+                } else if let Some(val) = &e.else_branch {
+                    self.execute(val)?;
                 }
-                Ok((Default::default(), this))
+                Ok(Default::default())
             }
             Stmt::Print(e) => {
-                let (ev, this) = self.evaluate(e.expression)?;
-                let value = this.stringify(&ev);
-                println!("{value}");
-                Ok((Default::default(), this))
+                let ev = self.evaluate(&e.expression)?;
+                let value = self.stringify(&ev);
+                writeln!(self.io.stdout, "{value}")
+                    .map_err(|err| RuntimeError::at(err.to_string(), e.span))?;
+                Ok(Default::default())
             }
             Stmt::Return(e) => {
-                let (value, this) = match e.value {
+                let value = match &e.value {
                     Some(val) => self.evaluate(val)?,
-                    None => (LoxType::Nil, self),
+                    None => LoxType::Nil,
                 };
-                Err(RuntimeError::as_return(value, this))
+                Err(RuntimeError::as_return(value))
             }
             Stmt::Var(e) => {
-                let (value, this) = self.evaluate(e.initializer)?;
-                this.env.borrow_mut().define(&e.name.lexeme, value);
-                Ok((Default::default(), this))
+                let value = self.evaluate(&e.initializer)?;
+                self.env.borrow_mut().define(&e.name.lexeme, value);
+                Ok(Default::default())
             }
             Stmt::While(e) => {
-                let mut this = self;
-                let mut value;
                 loop {
-                    let x = this.evaluate(e.cond.clone())?;
-                    (value, this) = x;
-                    if !this.is_truthy(&value) {
+                    let value = self.evaluate(&e.cond)?;
+                    if !self.is_truthy(&value) {
                         break;
                     }
-                    let body = Box::clone(&e.body);
-                    (_, this) = this.execute(*body)?;
+                    if let Err(err) = self.execute(&e.body) {
+                        match err.interrupt_kind {
+                            InterruptKind::Break => break,
+                            InterruptKind::Continue => (),
+                            _ => return Err(err),
+                        }
+                    }
+                    if let Some(increment) = &e.increment {
+                        self.evaluate(increment)?;
+                    }
+                    // loops that make no calls can still build cycles
+                    gc::maybe_collect();
                 }
-                Ok((Default::default(), this))
+                Ok(Default::default())
+            }
+            Stmt::Throw(e) => {
+                let value = self.evaluate(&e.value)?;
+                let mut err = RuntimeError::at(uncaught_message(&value), e.span);
+                err.trace = stack_trace(&self.call_stack, e.span.line);
+                err.interrupt_kind = InterruptKind::Throw(value);
+                Err(err)
             }
+            Stmt::Try(e) => {
+                let env = Environment::new(Some(Rc::clone(&self.env)));
+                let mut result = self.execute_block(&e.body, env);
+                if let (Err(err), Some((name, handler))) = (&result, &e.catch) {
+                    if matches!(
+                        err.interrupt_kind,
+                        InterruptKind::Builtin | InterruptKind::Throw(_)
+                    ) {
+                        let err = result.unwrap_err();
+                        let mut env = Environment::new(Some(Rc::clone(&self.env)));
+                        env.define(&name.lexeme, self.caught_value(err));
+                        result = self.execute_block(handler, env);
+                    }
+                }
+                if let Some(finally) = &e.finally {
+                    // an error or jump out of `finally` replaces the pending one
+                    let env = Environment::new(Some(Rc::clone(&self.env)));
+                    self.execute_block(finally, env)?;
+                }
+                result.map(|_| Default::default())
+            }
+            Stmt::Import(e) => {
+                let module = self.import(&e.path).map_err(|err| err.or_at(e.path.span))?;
+                if let Some(alias) = &e.alias {
+                    self.env
+                        .borrow_mut()
+                        .define(&alias.lexeme, LoxType::Module(module));
+                    return Ok(Default::default());
+                }
+                for name in &e.names {
+                    let value = module.globals.borrow().get(name).map_err(|_| {
+                        RuntimeError::at(
+                            format!("Module '{}' has no member '{}'.", module.name, name.lexeme),
+                            name.span,
+                        )
+                    })?;
+                    self.env.borrow_mut().define(&name.lexeme, value);
+                }
+                Ok(Default::default())
+            }
+            Stmt::Break(_) => Err(RuntimeError::interrupt(InterruptKind::Break)),
+            Stmt::Continue(_) => Err(RuntimeError::interrupt(InterruptKind::Continue)),
             Stmt::Class(cls) => {
-                let mut this = self;
-                let mut has_superclass = false;
-                let superclass = if let Some(sc) = cls.superclass {
-                    has_superclass = true;
-                    let superclass;
-                    (superclass, this) = this.evaluate(sc.into())?;
-                    let mut cons = None;
-                    let superclass_clone = superclass.clone();
-                    if match superclass {
-                        LoxType::Callable(callable) => {
-                            if matches!(callable.borrow().kind(), LoxCallableType::Class) {
-                                cons = Some(Rc::clone(&callable));
-                                false
-                            } else {
-                                true
-                            }
+                let superclass = if let Some(sc) = &cls.superclass {
+                    let superclass = self.lookup_var(&sc.name, sc.depth)?;
+                    let cons = match &superclass {
+                        LoxType::Callable(callable)
+                            if matches!(callable.borrow().kind(), LoxCallableType::Class) =>
+                        {
+                            Rc::clone(callable)
                         }
-                        _ => true,
-                    } {
-                        return Err(RuntimeError::new(
-                            "Superclass must be a class",
-                            cls.name.line,
-                            this,
-                        ));
-                    }
-                    this.env = ref_cell(Environment::new(Some(Rc::clone(&this.env))));
-                    this.env.borrow_mut().define("super", superclass_clone);
-                    cons
+                        _ => return Err(RuntimeError::at("Superclass must be a class", sc.span)),
+                    };
+                    self.env = gc::alloc(Environment::new(Some(Rc::clone(&self.env))));
+                    self.env.borrow_mut().define("super", superclass);
+                    Some(cons)
                 } else {
                     None
                 };
+                let has_superclass = superclass.is_some();
 
                 let methods = cls
                     .methods
-                    .into_iter()
+                    .iter()
                     .map(|method| {
-                        let name = method.name.lexeme.clone();
+                        let name = match method.method_kind {
+                            MethodKind::Setter => setter_name(&method.name.lexeme),
+                            _ => method.name.lexeme.clone(),
+                        };
                         let fun = LoxFunction::new(
-                            method,
-                            Rc::clone(&this.env),
+                            Rc::clone(method),
+                            Rc::clone(&self.env),
+                            Rc::clone(&self.globals),
                             if name == "init" {
                                 FunctionKind::Init
                             } else {
                                 FunctionKind::Function
                             },
                         );
-                        (name, ref_cell(fun))
+                        (name, gc::alloc(fun))
+                    })
+                    .collect::<HashMap<_, _>>();
+                let class_methods = cls
+                    .class_methods
+                    .iter()
+                    .map(|method| {
+                        let fun = LoxFunction::new(
+                            Rc::clone(method),
+                            Rc::clone(&self.env),
+                            Rc::clone(&self.globals),
+                            FunctionKind::Function,
+                        );
+                        (method.name.lexeme.clone(), gc::alloc(fun))
                     })
                     .collect::<HashMap<_, _>>();
 
-                let function = LoxClass::new(cls.name.lexeme.clone(), methods, superclass);
+                let function = LoxClass::new(cls.name.lexeme.clone(), methods, superclass)
+                    .with_class_methods(class_methods);
                 if has_superclass {
-                    let e = Rc::clone(this.env.borrow().enclosing.as_ref().expect("Impossible?"));
-                    this.env = e
+                    let e = Rc::clone(self.env.borrow().enclosing.as_ref().expect("Impossible?"));
+                    self.env = e
                 }
-                this.env
+                self.env
                     .borrow_mut()
                     .define(cls.name.lexeme.as_str(), function.into());
 
-                Ok((Default::default(), this))
+                Ok(Default::default())
             }
         }
     }
 
     pub fn new() -> Self {
-        let globals = ref_cell(initialize_globals());
+        let builtins = initialize_globals();
+        let globals = gc::alloc(builtins.clone());
         Self {
             env: Rc::clone(&globals),
             globals,
+            builtins,
+            call_stack: Vec::new(),
+            error_class: error_cls(),
+            modules: Default::default(),
+            nil_is_truthy: false,
+            io: Default::default(),
+            stack_size: DEFAULT_STACK_SIZE,
+            stack_base: stack_address(),
         }
     }
 
-    pub fn execute_block(self, statements: Vec<Stmt>, env: Environment) -> RuntimeResult<Self> {
-        let mut this = self;
-        let previous = Rc::clone(&this.env);
-
-        this.env = ref_cell(env);
-        for stmt in statements {
-            match this.execute(stmt) {
-                Err(mut e) => {
-                    e.interpreter.env = Rc::clone(&previous);
-                    return Err(e);
-                }
-                Ok((_, _this)) => {
-                    this = _this;
-                }
-            };
+    /// Load the module `path` names, running its top level in a fresh global
+    /// environment the first time it is imported.
+    fn import(&mut self, path: &Token) -> RuntimeResult<Rc<LoxModule>> {
+        let LoxType::String(name) = &path.literal else {
+            unreachable!("module paths are string literals")
+        };
+        let file = self
+            .modules
+            .find(name)
+            .map_err(|msg| RuntimeError::new(msg, 0))?;
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(module);
         }
-        this.env = previous;
-        Ok(this)
+        let source = fs::read_to_string(&file)
+            .map_err(|e| RuntimeError::new(format!("Could not read module \"{name}\": {e}"), 0))?;
+        let id = self
+            .modules
+            .begin(file.clone())
+            .map_err(|msg| RuntimeError::new(msg, 0))?;
+        let module = Rc::new(LoxModule {
+            name: module_name(&file),
+            path: file.clone(),
+            globals: gc::alloc(self.builtins.clone()),
+        });
+        let result = match parse_program(source, id) {
+            Ok(stmts) => {
+                let env = std::mem::replace(&mut self.env, Rc::clone(&module.globals));
+                let globals = std::mem::replace(&mut self.globals, Rc::clone(&module.globals));
+                let result = self.interpret(&stmts);
+                self.env = env;
+                self.globals = globals;
+                result.map(|_| ())
+            }
+            Err(errs) => Err(RuntimeError::new(load_error(name, &file, errs), 0)),
+        };
+        self.modules
+            .finish(result.is_ok().then(|| Rc::clone(&module)));
+        result.map(|_| module)
     }
-}
 
-// impl expr::Visitor<RuntimeResult<LoxType>> for Interpreter {
-
-//     fn Grouping(&mut self, e: &Grouping) -> RuntimeResult<LoxType> {
-//         self.evaluate(&*e.expression)
-//     }
-
-//     fn Literal(&mut self, e: &Literal) -> RuntimeResult<LoxType> {
-//         Ok(e.value.clone())
-//     }
-
-//     fn Unary(&mut self, e: &Unary) -> RuntimeResult<LoxType> {
-
-//     }
-
-//     fn Variable(&mut self, e: &expr::Variable) -> RuntimeResult<LoxType> {
-//         Ok(self.env.borrow().get(&e.name)?.clone())
-//     }
-
-//     fn Logical(&mut self, e: &expr::Logical) -> RuntimeResult<LoxType> {
-//         let left = self.evaluate(&*e.left)?;
-//         match e.operator.ty {
-//             TokenType::Or => {
-//                 if self.is_truthy(&left) {
-//                     return Ok(left);
-//                 }
-//             }
-//             TokenType::And => {
-//                 if !self.is_truthy(&left) {
-//                     return Ok(left);
-//                 }
-//             }
-//             _ => unreachable!(),
-//         }
-//         self.evaluate(&*e.right)
-//     }
-
-// }
+    pub fn execute_block(&mut self, statements: &[Stmt], env: Environment) -> RuntimeResult<()> {
+        let previous = std::mem::replace(&mut self.env, gc::alloc(env));
+        let res = statements
+            .iter()
+            .try_for_each(|stmt| self.execute(stmt).map(|_| ()));
+        self.env = previous;
+        res
+    }
+}
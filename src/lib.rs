@@ -0,0 +1,46 @@
+//! A Rust port of Lox from Crafting Interpreters.
+//!
+//! The [`Lox`] type is the embedding entry point: it owns an interpreter,
+//! evaluates source strings and exposes the global scope to Rust code.
+//! The individual pipeline stages ([`scanner::Scanner`], [`parser::Parser`],
+//! [`resolver::Resolver`] and [`interpreter::Interpreter`]) are public as well
+//! for tools that need to drive them directly. [`vm`] holds the bytecode
+//! compiler and stack VM that can run the same resolved programs.
+#![allow(
+    clippy::needless_return,
+    clippy::single_match,
+    clippy::upper_case_acronyms,
+    clippy::new_without_default
+)]
+
+pub mod ast_printer;
+pub mod embed;
+pub mod environment;
+pub mod errors;
+pub mod gc;
+pub mod globals;
+pub mod interpreter;
+pub mod lox_class;
+pub mod lox_file;
+pub mod lox_function;
+pub mod lox_io;
+pub mod lox_list;
+pub mod lox_map;
+pub mod lox_math;
+pub mod lox_string;
+pub mod module;
+pub mod native_class;
+pub mod native_method;
+pub mod number;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod syntax;
+pub mod tokens;
+pub mod vm;
+
+pub use embed::{Backend, Lox, LoxError, NativeFunction};
+pub use errors::Diagnostic;
+pub use lox_io::SharedBuffer;
+pub use native_class::NativeClass;
+pub use tokens::token::{LoxCallable, LoxType};
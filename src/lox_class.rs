@@ -2,24 +2,73 @@ use std::{any::Any, cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     errors::{LoxClassError, LoxClassResult, RuntimeResult},
+    gc::{self, Edge, Trace},
     interpreter::Interpreter,
     lox_function::LoxFunction,
-    tokens::token::{ref_cell, LoxCallable, LoxCallableType, LoxInstanceValue, LoxType, Token},
+    native_method::NativeMethodDef,
+    tokens::{
+        token::{LoxCallable, LoxCallableType, LoxInstanceValue, LoxType, Token},
+        token_type::TokenType,
+    },
 };
 
 pub type SuperClass = Option<Rc<RefCell<dyn LoxCallable>>>;
 
+/// Methods `==` and `!=` call on an instance that defines one, in order of
+/// preference. They receive the right operand.
+pub const EQ_METHODS: [&str; 2] = ["__eq__", "equals"];
+
+/// Method unary `-` calls on an instance that defines it.
+pub const NEG_METHOD: &str = "__neg__";
+
+/// The method a binary operator calls on an instance on its left that
+/// defines it. It receives the right operand.
+pub fn operator_method(op: TokenType) -> Option<&'static str> {
+    use TokenType::*;
+    Some(match op {
+        Plus => "__add__",
+        Minus => "__sub__",
+        Star => "__mul__",
+        Slash => "__div__",
+        Percent => "__mod__",
+        Less => "__lt__",
+        LessEqual => "__le__",
+        Greater => "__gt__",
+        GreaterEqual => "__ge__",
+        _ => return None,
+    })
+}
+
+/// Key of the setter for `name` among the methods of a class, which keeps
+/// it apart from a getter of the same name.
+pub fn setter_name(name: &str) -> String {
+    format!("{name}=")
+}
+
 #[derive(Debug, Clone)]
 pub struct LoxClass {
     name: String,
     methods: HashMap<String, Rc<RefCell<LoxFunction>>>,
     superclass: SuperClass,
+    /// Methods of the class itself, declared with `class name() { }`. The
+    /// class acts as the single instance of its metaclass, so they are bound
+    /// with `this` as the class.
+    class_methods: HashMap<String, Rc<RefCell<LoxFunction>>>,
+    /// Methods implemented in Rust, see
+    /// [`NativeClass`](crate::native_class::NativeClass).
+    native_methods: HashMap<&'static str, NativeMethodDef>,
+    /// Fields of the class itself, such as `File.exists`. Shared between
+    /// the copies of the class, since scripts can assign them at any time.
+    statics: Rc<RefCell<HashMap<String, LoxType>>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct LoxInstance {
     pub this: LoxClass,
-    native_fields: HashMap<String, Rc<RefCell<dyn Any>>>,
+    /// State of an instance of a native class, such as the open file of a
+    /// `File`. It cannot reference Lox values, since the collector does not
+    /// see it.
+    native: Option<Rc<dyn Any>>,
     fields: HashMap<String, LoxType>,
 }
 
@@ -33,8 +82,51 @@ impl LoxClass {
             name: name.into(),
             methods,
             superclass,
+            class_methods: Default::default(),
+            native_methods: Default::default(),
+            statics: Default::default(),
+        }
+    }
+    pub fn with_class_methods(
+        mut self,
+        class_methods: HashMap<String, Rc<RefCell<LoxFunction>>>,
+    ) -> Self {
+        self.class_methods = class_methods;
+        self
+    }
+    pub fn define_native_method(&mut self, method: NativeMethodDef) {
+        self.native_methods.insert(method.name, method);
+    }
+    /// Look up a native method, including inherited ones.
+    pub fn find_native_method(&self, name: &str) -> Option<NativeMethodDef> {
+        match self.native_methods.get(name) {
+            Some(method) => Some(*method),
+            None => self
+                .superclass
+                .as_ref()?
+                .borrow()
+                .constructor()?
+                .find_native_method(name),
         }
     }
+    pub fn define_static<T: Into<String>>(&self, name: T, value: LoxType) {
+        self.statics.borrow_mut().insert(name.into(), value);
+    }
+    /// Look up a member of the class itself: its own fields first, then its
+    /// class methods, then those inherited from the superclass.
+    pub fn get_static(&self, name: &str) -> Option<LoxInstanceValue> {
+        if let Some(value) = self.statics.borrow().get(name) {
+            return Some(LoxInstanceValue::Free(value.clone()));
+        }
+        if let Some(method) = self.class_methods.get(name) {
+            return Some(LoxInstanceValue::Bound(Rc::clone(method)));
+        }
+        self.superclass
+            .as_ref()?
+            .borrow()
+            .constructor()
+            .and_then(|sc| sc.get_static(name))
+    }
     pub fn find_method<T: Into<String> + Clone>(
         &self,
         method: T,
@@ -58,34 +150,72 @@ impl LoxClass {
     }
 }
 
+/// Look up `name` on `value` if it is a class, returning `None` for values
+/// that are not classes. Class methods are bound to `value`, so inherited
+/// ones see the subclass as `this`.
+pub fn static_member(value: &LoxType, name: &str) -> Option<Option<LoxType>> {
+    match value {
+        LoxType::Callable(callable) => {
+            let member = callable.borrow().constructor()?.get_static(name);
+            Some(member.map(|member| match member {
+                LoxInstanceValue::Free(value) => value,
+                LoxInstanceValue::Bound(method) => method.borrow().bind(value.clone()).into(),
+                LoxInstanceValue::Native(method) => method.bind(value.clone()).into(),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Set the field `name` on `value` if it is a class, returning `None` for
+/// values that are not classes.
+pub fn set_static(value: &LoxType, name: &str, field: LoxType) -> Option<()> {
+    match value {
+        LoxType::Callable(callable) => {
+            callable.borrow().constructor()?.define_static(name, field);
+            Some(())
+        }
+        _ => None,
+    }
+}
+
 impl LoxInstance {
     pub fn new(this: LoxClass) -> Self {
         Self {
             this,
             fields: Default::default(),
-            native_fields: Default::default(),
-        }
-    }
-    pub fn get(&self, name: Token) -> LoxClassResult<LoxInstanceValue> {
-        match self.fields.get(&name.lexeme) {
-            Some(val) => Ok(LoxInstanceValue::Free(val.clone().into())),
-            None => match self.this.find_method(&name.lexeme) {
-                Some(val) => Ok(LoxInstanceValue::Bound(val)),
-                None => Err(LoxClassError::new(
-                    format!("Undefined property '{}'.", &name.lexeme),
-                    name.line,
-                )),
-            },
+            native: None,
+        }
+    }
+    /// Look up a field, then a method and finally a native method.
+    pub fn get(&self, name: &Token) -> LoxClassResult<LoxInstanceValue> {
+        if let Some(val) = self.fields.get(&name.lexeme) {
+            return Ok(LoxInstanceValue::Free(val.clone()));
+        }
+        if let Some(method) = self.this.find_method(&name.lexeme) {
+            return Ok(LoxInstanceValue::Bound(method));
+        }
+        match self.this.find_native_method(&name.lexeme) {
+            Some(method) => Ok(LoxInstanceValue::Native(method)),
+            None => Err(LoxClassError::at(
+                format!("Undefined property '{}'.", &name.lexeme),
+                name.span,
+            )),
         }
     }
-    pub fn set(&mut self, name: Token, value: LoxType) -> () {
+    pub fn set(&mut self, name: Token, value: LoxType) {
         self.fields.insert(name.lexeme, value);
     }
-    pub fn store_native<T: Into<String>>(&mut self, key: T, val: Rc<RefCell<dyn Any>>) -> () {
-        self.native_fields.insert(key.into(), val);
+    /// Attach the state of a native class to the instance, replacing any
+    /// previous state.
+    pub fn set_native<T: Any>(&mut self, state: T) {
+        self.native = Some(Rc::new(RefCell::new(state)));
     }
-    pub fn get_native(&mut self, key: &str) -> Option<&Rc<RefCell<dyn Any>>> {
-        self.native_fields.get(key)
+    /// The native state of the instance, if it has state of type `T`. It is
+    /// shared, so native methods can use it without keeping the instance
+    /// borrowed.
+    pub fn native<T: Any>(&self) -> Option<Rc<RefCell<T>>> {
+        Rc::clone(self.native.as_ref()?).downcast().ok()
     }
 }
 
@@ -98,40 +228,81 @@ impl LoxCallable for LoxClass {
     }
     fn arity(&self) -> usize {
         match self.find_method("init") {
-            None => 0,
             Some(init) => init.borrow().arity(),
+            None => self.find_native_method("init").map_or(0, |init| init.arity),
         }
     }
     fn name(&self) -> String {
         return self.name.clone();
     }
-    // fn clone_box(&self) -> Box<dyn LoxCallable> {
-    //     Box::new(self.clone())
-    // }
-    fn call(
-        &mut self,
-        interpreter: Interpreter,
-        args: Vec<LoxType>,
-    ) -> RuntimeResult<(LoxType, Interpreter)> {
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
         let inst = LoxInstance::new(self.clone());
         if let Some(initializer) = self.find_method("init") {
-            let (res, interp) = initializer
+            return initializer
                 .borrow()
                 .bind(inst.into())
-                .call(interpreter, args)?;
-            return Ok((res, interp));
+                .call(interpreter, args);
+        }
+        let inst: LoxType = inst.into();
+        if let Some(initializer) = self.find_native_method("init") {
+            (initializer.fun)(interpreter, &inst, args)?;
+        }
+        Ok(inst)
+    }
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        Trace::trace(self, visit)
+    }
+    fn clear(&self) {
+        self.statics.borrow_mut().clear();
+    }
+}
+
+impl LoxClass {
+    /// Report the references a copy of the class holds on its own. The
+    /// statics are shared between copies, so only the class object itself
+    /// reports them.
+    fn trace_methods(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        self.methods
+            .values()
+            .for_each(|method| visit(Edge::Method(method)));
+        if let Some(superclass) = &self.superclass {
+            visit(Edge::Callable(superclass));
         }
-        Ok((inst.into(), interpreter))
+        self.class_methods
+            .values()
+            .for_each(|method| visit(Edge::Method(method)));
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        self.trace_methods(visit);
+        if let Ok(statics) = self.statics.try_borrow() {
+            statics.values().for_each(|value| visit(Edge::Value(value)));
+        }
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        self.this.trace_methods(visit);
+        self.fields
+            .values()
+            .for_each(|value| visit(Edge::Value(value)));
+    }
+    fn clear(&mut self) {
+        self.fields.clear();
+        self.native = None;
     }
 }
 
 impl From<LoxClass> for LoxType {
     fn from(value: LoxClass) -> Self {
-        LoxType::Callable(ref_cell((value)))
+        gc::alloc_callable(value)
     }
 }
 impl From<LoxInstance> for LoxType {
     fn from(value: LoxInstance) -> Self {
-        LoxType::Data(ref_cell((value)))
+        LoxType::Data(gc::alloc(value))
     }
 }
@@ -0,0 +1,274 @@
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    errors::{RuntimeError, RuntimeResult},
+    gc, gen_native_func,
+    interpreter::Interpreter,
+    lox_class::LoxClass,
+    native_class::NativeClass,
+    number::exact_int,
+    tokens::{
+        token::{ref_cell, LoxCallable, LoxCallableType, LoxType, Token},
+        token_type::TokenType,
+    },
+};
+
+/// The open file behind a `File` instance, stored as its native state.
+/// Reads are buffered, writes go straight to the file. Closing drops the
+/// file.
+#[derive(Debug)]
+struct FileHandle {
+    file: Option<BufReader<File>>,
+    binary: bool,
+}
+
+fn error<T: Into<String>>(message: T) -> RuntimeError {
+    RuntimeError::new(message, 0)
+}
+
+fn io_error(err: io::Error) -> RuntimeError {
+    error(err.to_string())
+}
+
+/// Open options for a mode string like `"r"`, `"w+"` or `"ab"`, and whether
+/// the file is opened in binary mode.
+fn parse_mode(mode: &str) -> Option<(OpenOptions, bool)> {
+    let binary = mode.contains('b');
+    let mut options = OpenOptions::new();
+    match &*mode.replacen('b', "", 1) {
+        "r" => options.read(true),
+        "r+" => options.read(true).write(true),
+        "w" => options.write(true).create(true).truncate(true),
+        "w+" => options.read(true).write(true).create(true).truncate(true),
+        "a" => options.append(true).create(true),
+        "a+" => options.read(true).append(true).create(true),
+        _ => return None,
+    };
+    Some((options, binary))
+}
+
+fn this_handle(receiver: &LoxType) -> RuntimeResult<Rc<RefCell<FileHandle>>> {
+    match receiver {
+        LoxType::Data(inst) => inst
+            .borrow()
+            .native::<FileHandle>()
+            .ok_or_else(|| error("File was not opened.")),
+        _ => unreachable!("File method bound to a non instance"),
+    }
+}
+
+/// Run `f` on the open file of `receiver`, failing if it was closed.
+fn with_file<T>(
+    receiver: &LoxType,
+    f: impl FnOnce(&mut BufReader<File>, bool) -> RuntimeResult<T>,
+) -> RuntimeResult<T> {
+    let handle = this_handle(receiver)?;
+    let mut handle = handle.borrow_mut();
+    let binary = handle.binary;
+    match &mut handle.file {
+        Some(file) => f(file, binary),
+        None => Err(error("File is closed.")),
+    }
+}
+
+fn text_only(binary: bool, method: &str) -> RuntimeResult<()> {
+    match binary {
+        true => Err(error(format!(
+            "{method}() is not available in binary mode."
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Read the next line without its line ending, or `None` at the end of the
+/// file.
+fn read_line(file: &mut BufReader<File>) -> RuntimeResult<Option<String>> {
+    let mut line = String::new();
+    if file.read_line(&mut line).map_err(io_error)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+fn path_arg(path: &LoxType) -> RuntimeResult<&str> {
+    match path {
+        LoxType::String(path) => Ok(path),
+        _ => Err(error("File path must be a string.")),
+    }
+}
+
+fn init(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let path = path_arg(&args[0])?;
+    let LoxType::String(mode) = &args[1] else {
+        return Err(error("File mode must be a string."));
+    };
+    let Some((options, binary)) = parse_mode(mode) else {
+        return Err(error(format!(
+            "Invalid file mode \"{mode}\". Expected r, w, a, r+, w+ or a+, optionally with b."
+        )));
+    };
+    let file = options.open(path).map_err(io_error)?;
+
+    let LoxType::Data(inst) = receiver else {
+        unreachable!("File method bound to a non instance")
+    };
+    let mut inst = inst.borrow_mut();
+    inst.set_native(FileHandle {
+        file: Some(BufReader::new(file)),
+        binary,
+    });
+    let field = |name: &str| Token::dummy(name, TokenType::Identifier);
+    inst.set(field("path"), path.into());
+    inst.set(field("mode"), mode.as_str().into());
+    Ok(LoxType::Nil)
+}
+
+fn read(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, binary| {
+        if binary {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes).map_err(io_error)?;
+            let bytes = bytes.into_iter().map(|b| LoxType::Int(b.into())).collect();
+            Ok(LoxType::List(gc::alloc(bytes)))
+        } else {
+            let mut s = String::new();
+            file.read_to_string(&mut s).map_err(io_error)?;
+            Ok(LoxType::String(s))
+        }
+    })
+}
+
+fn readline(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, binary| {
+        text_only(binary, "readline")?;
+        Ok(read_line(file)?.map_or(LoxType::Nil, LoxType::String))
+    })
+}
+
+/// Read the rest of the file at once, as a list of lines. `readline` is the
+/// way to go through a large file without holding all of it in memory.
+fn lines(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, binary| {
+        text_only(binary, "lines")?;
+        let mut lines = Vec::new();
+        while let Some(line) = read_line(file)? {
+            lines.push(LoxType::String(line));
+        }
+        Ok(LoxType::List(gc::alloc(lines)))
+    })
+}
+
+fn write(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, binary| {
+        let bytes = match (&args[0], binary) {
+            (LoxType::String(text), false) => text.clone().into_bytes(),
+            (LoxType::List(list), true) => list
+                .borrow()
+                .iter()
+                .map(|b| match b {
+                    LoxType::Int(b) => u8::try_from(*b).ok(),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| error("Bytes must be integers between 0 and 255."))?,
+            (_, false) => return Err(error("write() expects a string.")),
+            (_, true) => return Err(error("write() expects a list of bytes in binary mode.")),
+        };
+        // drop what was read ahead so the write lands at the position
+        // `tell()` reports
+        let position = file.stream_position().map_err(io_error)?;
+        file.seek(SeekFrom::Start(position)).map_err(io_error)?;
+        file.get_mut().write_all(&bytes).map_err(io_error)?;
+        Ok(LoxType::Nil)
+    })
+}
+
+fn seek(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let offset = match args[0].as_number() {
+        Some(n) => exact_int(n.as_f64()).and_then(|n| u64::try_from(n).ok()),
+        None => None,
+    };
+    let Some(offset) = offset else {
+        return Err(error("seek() expects a non-negative integer offset."));
+    };
+    with_file(receiver, |file, _| {
+        file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        Ok(LoxType::Nil)
+    })
+}
+
+fn tell(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, _| {
+        let position = file.stream_position().map_err(io_error)?;
+        Ok(LoxType::Int(position as i64))
+    })
+}
+
+fn flush(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    with_file(receiver, |file, _| {
+        file.get_mut().flush().map_err(io_error)?;
+        Ok(LoxType::Nil)
+    })
+}
+
+fn close(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    this_handle(receiver)?.borrow_mut().file = None;
+    Ok(LoxType::Nil)
+}
+
+gen_native_func!(
+    FileExists as "exists",
+    i,
+    Path::new(path_arg(path)?).exists().into(),
+    path
+);
+
+gen_native_func!(
+    FileSize as "size",
+    i,
+    {
+        let metadata = fs::metadata(path_arg(path)?).map_err(io_error)?;
+        LoxType::Int(metadata.len() as i64)
+    },
+    path
+);
+
+gen_native_func!(
+    FileRemove as "remove",
+    i,
+    {
+        fs::remove_file(path_arg(path)?).map_err(io_error)?;
+        LoxType::Nil
+    },
+    path
+);
+
+/// The native `File(path, mode)` class.
+pub fn file_cls() -> LoxClass {
+    NativeClass::new("File")
+        .init(2, init)
+        .method("read", 0, read)
+        .method("readline", 0, readline)
+        .method("lines", 0, lines)
+        .method("write", 1, write)
+        .method("seek", 1, seek)
+        .method("tell", 0, tell)
+        .method("flush", 0, flush)
+        .method("close", 0, close)
+        .static_member("exists", FileExists.into())
+        .static_member("size", FileSize.into())
+        .static_member("remove", FileRemove.into())
+        .build()
+}
@@ -1,10 +1,11 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    environment::Environment,
-    errors::{InterruptKind, RuntimeError, RuntimeResult},
+    environment::{EnclosingEnv, Environment},
+    errors::{InterruptKind, RuntimeResult},
+    gc::{self, Edge, Trace},
     interpreter::Interpreter,
-    syntax::stmt::Function,
+    syntax::stmt::{Function, MethodKind},
     tokens::token::{LoxCallable, LoxCallableType, LoxType, Token},
 };
 
@@ -15,31 +16,43 @@ pub enum FunctionKind {
 }
 #[derive(Debug, Clone)]
 pub struct LoxFunction {
-    declaration: Function,
+    declaration: Rc<Function>,
     closure: Rc<RefCell<Environment>>,
+    /// Global environment of the module the function was declared in.
+    globals: EnclosingEnv,
     kind: FunctionKind,
 }
 
 impl LoxFunction {
     pub fn new(
-        declaration: Function,
+        declaration: Rc<Function>,
         closure: Rc<RefCell<Environment>>,
+        globals: EnclosingEnv,
         kind: FunctionKind,
     ) -> Self {
         Self {
             declaration,
             closure,
+            globals,
             kind,
         }
     }
+    pub fn method_kind(&self) -> MethodKind {
+        self.declaration.method_kind
+    }
     pub fn bind(&self, to: LoxType) -> Self {
         let k = self.kind;
-        if !matches!(to, LoxType::Data(_)) {
+        if !matches!(to, LoxType::Data(_) | LoxType::Callable(_)) {
             panic!("Cannot bind function to a non instance object!")
         }
         let mut env = Environment::new(Some(Rc::clone(&self.closure)));
         env.define("this", to);
-        return Self::new(self.declaration.clone(), Rc::new(RefCell::new(env)), k);
+        return Self::new(
+            Rc::clone(&self.declaration),
+            gc::alloc(env),
+            Rc::clone(&self.globals),
+            k,
+        );
     }
 }
 
@@ -53,53 +66,45 @@ impl LoxCallable for LoxFunction {
     fn name(&self) -> String {
         return self.declaration.name.lexeme.clone();
     }
-    // fn clone_box(&self) -> Box<dyn LoxCallable> {
-    //     Box::new(Self::new(
-    //         self.declaration.clone(),
-    //         Rc::clone(&self.closure),
-    //         self.kind,
-    //     ))
-    // }
-    fn call(
-        &mut self,
-        interpreter: Interpreter,
-        args: Vec<LoxType>,
-    ) -> RuntimeResult<(LoxType, Interpreter)> {
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
         let mut env = Environment::new(Some(Rc::clone(&self.closure)));
 
         self.declaration
             .params
             .iter()
-            .zip(&args)
+            .zip(args)
             .for_each(|(param, arg)| {
-                env.define(&param.lexeme, arg.clone());
+                env.define(&param.lexeme, arg);
             });
 
-        match interpreter.execute_block(self.declaration.body.clone(), env) {
+        let globals = std::mem::replace(&mut interpreter.globals, Rc::clone(&self.globals));
+        let result = interpreter.execute_block(&self.declaration.body, env);
+        interpreter.globals = globals;
+        let value = match result {
             Err(err) => match err.interrupt_kind {
-                InterruptKind::Builtin => Err(err),
-                InterruptKind::Return(val) => {
-                    if matches!(self.kind, FunctionKind::Init) {
-                        return match self.closure.borrow().get_at(&Token::dummy_this(), 0) {
-                            Err(e) => Err(RuntimeError::new(e.message, e.line, err.interpreter)),
-                            Ok(this) => Ok((this, err.interpreter)),
-                        };
-                    }
-                    Ok((val, err.interpreter))
-                }
-            },
-            Ok(ret) => match self.kind {
-                FunctionKind::Init => match self.closure.borrow().get_at(&Token::dummy_this(), 0) {
-                    Err(e) => Err(RuntimeError::new(e.message, e.line, ret)),
-                    Ok(val) => Ok((val, ret)),
-                },
-                _ => Ok((LoxType::Nil.into(), ret)),
+                InterruptKind::Return(val) => val,
+                _ => return Err(err),
             },
+            Ok(()) => LoxType::Nil,
+        };
+        match self.kind {
+            FunctionKind::Init => Ok(self.closure.borrow().get_at(&Token::dummy_this(), 0)?),
+            _ => Ok(value),
         }
     }
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        Trace::trace(self, visit)
+    }
 }
+impl Trace for LoxFunction {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        visit(Edge::Env(&self.closure));
+        visit(Edge::Env(&self.globals));
+    }
+}
+
 impl From<LoxFunction> for LoxType {
     fn from(value: LoxFunction) -> Self {
-        LoxType::Callable(Rc::new(RefCell::new(value)))
+        gc::alloc_callable(value)
     }
 }
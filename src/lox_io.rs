@@ -0,0 +1,308 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    environment::Environment,
+    errors::{RuntimeError, RuntimeResult},
+    gc, gen_native_func,
+    interpreter::Interpreter,
+    module::LoxModule,
+    number::{exact_int, Number},
+    tokens::token::{ref_cell, LoxCallable, LoxCallableType, LoxType},
+};
+
+/// The streams scripts read from and write to, through `print` and the `io`
+/// module. They default to the process's standard streams and can be
+/// replaced, for example to capture the output of an embedded script.
+pub struct IoStreams {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    /// `None` reads the process's standard input, locking it only while
+    /// reading so that the REPL can share it.
+    stdin: Option<Box<dyn BufRead>>,
+}
+
+impl Default for IoStreams {
+    fn default() -> Self {
+        Self {
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            stdin: None,
+        }
+    }
+}
+
+impl IoStreams {
+    pub fn set_stdin<R: BufRead + 'static>(&mut self, reader: R) {
+        self.stdin = Some(Box::new(reader));
+    }
+
+    fn read<T>(&mut self, f: impl FnOnce(&mut dyn BufRead) -> io::Result<T>) -> io::Result<T> {
+        match &mut self.stdin {
+            Some(reader) => f(reader),
+            None => f(&mut io::stdin().lock()),
+        }
+    }
+}
+
+impl std::fmt::Debug for IoStreams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("IoStreams")
+    }
+}
+
+/// An in-memory writer whose clones share the same buffer, so output
+/// written through one can be read back through another.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, decoded lossily.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Take everything written so far, leaving the buffer empty.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn io_error(err: io::Error) -> RuntimeError {
+    RuntimeError::new(err.to_string(), 0)
+}
+
+/// Read a line from the input without its line ending, or `nil` at the end
+/// of the input.
+fn read_line(interpreter: &mut Interpreter) -> RuntimeResult<LoxType> {
+    let mut line = String::new();
+    let read = interpreter.io.read(|stdin| stdin.read_line(&mut line));
+    if read.map_err(io_error)? == 0 {
+        return Ok(LoxType::Nil);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(line.into())
+}
+
+fn write_str(out: &mut dyn Write, s: &str) -> RuntimeResult<()> {
+    out.write_all(s.as_bytes()).map_err(io_error)
+}
+
+gen_native_func!(
+    Input as "input",
+    interpreter,
+    {
+        let prompt = interpreter.stringify(prompt);
+        write_str(&mut interpreter.io.stdout, &prompt)?;
+        interpreter.io.stdout.flush().map_err(io_error)?;
+        read_line(interpreter)?
+    },
+    prompt
+);
+
+gen_native_func!(Readline as "readline", interpreter, read_line(interpreter)?);
+
+gen_native_func!(ReadAll as "read_all", interpreter, {
+    let mut s = String::new();
+    interpreter
+        .io
+        .read(|stdin| stdin.read_to_string(&mut s))
+        .map_err(io_error)?;
+    s.into()
+});
+
+gen_native_func!(
+    WriteFn as "write",
+    interpreter,
+    {
+        let text = interpreter.stringify(value);
+        write_str(&mut interpreter.io.stdout, &text)?;
+        LoxType::Nil
+    },
+    value
+);
+
+gen_native_func!(
+    Eprint as "eprint",
+    interpreter,
+    {
+        let line = interpreter.stringify(value) + "\n";
+        write_str(&mut interpreter.io.stderr, &line)?;
+        LoxType::Nil
+    },
+    value
+);
+
+gen_native_func!(Flush as "flush", interpreter, {
+    interpreter.io.stdout.flush().map_err(io_error)?;
+    LoxType::Nil
+});
+
+fn format_args(format: &LoxType, args: &LoxType) -> RuntimeResult<String> {
+    let LoxType::String(format) = format else {
+        return Err(RuntimeError::new(
+            format!("Format must be a string, got {}.", format.repr()),
+            0,
+        ));
+    };
+    let LoxType::List(args) = args else {
+        return Err(RuntimeError::new(
+            format!("Format arguments must be a list, got {}.", args.repr()),
+            0,
+        ));
+    };
+    sprintf(format, &args.borrow()).map_err(|msg| RuntimeError::new(msg, 0))
+}
+
+gen_native_func!(
+    Format as "format",
+    i,
+    format_args(format, args)?.into(),
+    format,
+    args
+);
+
+gen_native_func!(
+    Printf as "printf",
+    interpreter,
+    {
+        let text = format_args(format, args)?;
+        write_str(&mut interpreter.io.stdout, &text)?;
+        LoxType::Nil
+    },
+    format,
+    args
+);
+
+/// Format `args` the way C's `printf` would.
+///
+/// Conversions look like `%[-][0][width][.precision]type`, where the type is
+/// `s` for any value, `d` for integers, `f` for numbers with `precision`
+/// decimals (6 by default) and `x` or `X` for integers in hexadecimal. `%%`
+/// is a literal `%`.
+pub fn sprintf(format: &str, args: &[LoxType]) -> Result<String, String> {
+    let mut out = String::with_capacity(format.len());
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'%').is_some() {
+            out.push('%');
+            continue;
+        }
+        let left = chars.next_if_eq(&'-').is_some();
+        let zero = chars.next_if_eq(&'0').is_some();
+        let mut width = 0;
+        while let Some(d) = chars.next_if(char::is_ascii_digit) {
+            width = width * 10 + d.to_digit(10).expect("digit") as usize;
+        }
+        let mut precision = None;
+        if chars.next_if_eq(&'.').is_some() {
+            let mut p = 0;
+            while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                p = p * 10 + d.to_digit(10).expect("digit") as usize;
+            }
+            precision = Some(p);
+        }
+        let Some(ty) = chars.next() else {
+            return Err("Incomplete format specifier at the end of the format.".into());
+        };
+        let Some(arg) = args.next() else {
+            return Err("Not enough arguments for the format.".into());
+        };
+        let int = || {
+            match arg.as_number() {
+                Some(Number::Int(i)) => Ok(i),
+                Some(Number::Float(f)) => exact_int(f).ok_or(()),
+                None => Err(()),
+            }
+            .map_err(|_| format!("%{ty} expects an integer, got {}.", arg.repr()))
+        };
+        let (text, numeric) = match ty {
+            's' => {
+                let s = arg.to_string();
+                match precision {
+                    Some(p) => (s.chars().take(p).collect(), false),
+                    None => (s, false),
+                }
+            }
+            'd' => (int()?.to_string(), true),
+            'x' => (format!("{:x}", int()?), true),
+            'X' => (format!("{:X}", int()?), true),
+            'f' => match arg.as_number() {
+                Some(n) => (format!("{:.*}", precision.unwrap_or(6), n.as_f64()), true),
+                None => return Err(format!("%f expects a number, got {}.", arg.repr())),
+            },
+            _ => return Err(format!("Unknown format specifier '%{ty}'.")),
+        };
+        let pad = width.saturating_sub(text.chars().count());
+        if left {
+            out.push_str(&text);
+            out.extend(std::iter::repeat_n(' ', pad));
+        } else if zero && numeric {
+            // zeros go after the sign
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => ("-", digits),
+                None => ("", &text[..]),
+            };
+            out.push_str(sign);
+            out.extend(std::iter::repeat_n('0', pad));
+            out.push_str(digits);
+        } else {
+            out.extend(std::iter::repeat_n(' ', pad));
+            out.push_str(&text);
+        }
+    }
+    if args.next().is_some() {
+        return Err("Too many arguments for the format.".into());
+    }
+    Ok(out)
+}
+
+/// The built-in `io` module.
+pub fn io_module() -> LoxType {
+    let mut env = Environment::new(None);
+    let natives: [(&str, LoxType); 8] = [
+        ("input", Input.into()),
+        ("readline", Readline.into()),
+        ("read_all", ReadAll.into()),
+        ("write", WriteFn.into()),
+        ("eprint", Eprint.into()),
+        ("format", Format.into()),
+        ("printf", Printf.into()),
+        ("flush", Flush.into()),
+    ];
+    for (name, native) in natives {
+        env.define(name, native);
+    }
+    LoxType::Module(Rc::new(LoxModule {
+        name: "io".into(),
+        path: Default::default(),
+        globals: gc::alloc(env),
+    }))
+}
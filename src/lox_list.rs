@@ -0,0 +1,165 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    errors::{RuntimeError, RuntimeResult},
+    gc::{self, Edge, Trace},
+    interpreter::Interpreter,
+    native_method::{MethodFn, NativeMethod},
+    tokens::token::{LoxType, Token},
+};
+
+pub type LoxList = Rc<RefCell<Vec<LoxType>>>;
+
+const METHODS: &[(&str, usize, MethodFn)] = &[
+    ("push", 1, push),
+    ("pop", 0, pop),
+    ("len", 0, len),
+    ("insert", 2, insert),
+    ("remove", 1, remove),
+    ("slice", 2, slice),
+];
+
+/// Look up a built-in method on `list`, bound to it.
+pub fn get_method(list: &LoxList, name: &Token) -> RuntimeResult<LoxType> {
+    match METHODS.iter().find(|(n, ..)| *n == name.lexeme) {
+        Some(&(n, arity, fun)) => {
+            Ok(NativeMethod::new(n, arity, LoxType::List(Rc::clone(list)), fun).into())
+        }
+        None => Err(RuntimeError::at(
+            format!("Undefined property '{}'.", name.lexeme),
+            name.span,
+        )),
+    }
+}
+
+/// Convert `index` into a position in a list of length `len`. With
+/// `inclusive` the position one past the end is accepted as well.
+pub fn to_index(index: &LoxType, len: usize, inclusive: bool) -> Result<usize, String> {
+    let n = match index {
+        LoxType::Int(n) => usize::try_from(*n).map_err(|_| format!("Invalid list index {n}."))?,
+        LoxType::Float(n) if n.fract() == 0.0 && *n >= 0.0 => *n as usize,
+        LoxType::Float(_) => return Err(format!("Invalid list index {index}.")),
+        _ => return Err("List index must be a number.".to_owned()),
+    };
+    if n < len || (inclusive && n == len) {
+        Ok(n)
+    } else {
+        Err(format!("List index {n} out of range for length {len}."))
+    }
+}
+
+/// Why `object[index]` failed.
+pub enum IndexError {
+    /// The index or key is invalid for the list or map.
+    Index(String),
+    /// The object is neither a list nor a map.
+    NotIndexable,
+}
+
+impl IndexError {
+    pub fn message(self) -> String {
+        match self {
+            IndexError::Index(msg) => msg,
+            IndexError::NotIndexable => "Only lists and maps can be indexed.".to_owned(),
+        }
+    }
+}
+
+/// `object[index]` for a list or a map.
+pub fn get_index(object: &LoxType, index: &LoxType) -> Result<LoxType, IndexError> {
+    match object {
+        LoxType::List(list) => {
+            let list = list.borrow();
+            let i = to_index(index, list.len(), false).map_err(IndexError::Index)?;
+            Ok(list[i].clone())
+        }
+        LoxType::Map(map) => match map.borrow().get(index) {
+            Ok(Some(value)) => Ok(value.clone()),
+            Ok(None) => Err(IndexError::Index(format!(
+                "Key {} not found in map.",
+                index.repr()
+            ))),
+            Err(msg) => Err(IndexError::Index(msg)),
+        },
+        _ => Err(IndexError::NotIndexable),
+    }
+}
+
+/// `object[index] = value` for a list or a map.
+pub fn set_index(object: &LoxType, index: LoxType, value: LoxType) -> Result<(), IndexError> {
+    match object {
+        LoxType::List(list) => {
+            let mut list = list.borrow_mut();
+            let i = to_index(&index, list.len(), false).map_err(IndexError::Index)?;
+            list[i] = value;
+            Ok(())
+        }
+        LoxType::Map(map) => map
+            .borrow_mut()
+            .insert(index, value)
+            .map_err(IndexError::Index),
+        _ => Err(IndexError::NotIndexable),
+    }
+}
+
+fn this(receiver: &LoxType) -> &LoxList {
+    match receiver {
+        LoxType::List(list) => list,
+        _ => unreachable!("list method bound to a non list"),
+    }
+}
+
+fn push(_: &mut Interpreter, receiver: &LoxType, mut args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    this(receiver).borrow_mut().push(args.remove(0));
+    Ok(LoxType::Nil)
+}
+
+fn pop(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    match this(receiver).borrow_mut().pop() {
+        Some(value) => Ok(value),
+        None => Err(RuntimeError::new("Cannot pop from an empty list.", 0)),
+    }
+}
+
+fn len(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).borrow().len().into())
+}
+
+fn insert(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let mut list = this(receiver).borrow_mut();
+    let [index, value]: [LoxType; 2] = args.try_into().expect("arity checked");
+    let index = to_index(&index, list.len(), true).map_err(|e| RuntimeError::new(e, 0))?;
+    list.insert(index, value);
+    Ok(LoxType::Nil)
+}
+
+fn remove(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let mut list = this(receiver).borrow_mut();
+    let index = to_index(&args[0], list.len(), false).map_err(|e| RuntimeError::new(e, 0))?;
+    Ok(list.remove(index))
+}
+
+fn slice(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let list = this(receiver).borrow();
+    let start = to_index(&args[0], list.len(), true).map_err(|e| RuntimeError::new(e, 0))?;
+    let end = match &args[1] {
+        LoxType::Nil => list.len(),
+        end => to_index(end, list.len(), true).map_err(|e| RuntimeError::new(e, 0))?,
+    };
+    if start > end {
+        return Err(RuntimeError::new(
+            format!("Slice start {start} is after its end {end}."),
+            0,
+        ));
+    }
+    Ok(LoxType::List(gc::alloc(list[start..end].to_vec())))
+}
+
+impl Trace for Vec<LoxType> {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        self.iter().for_each(|value| visit(Edge::Value(value)));
+    }
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+}
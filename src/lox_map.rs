@@ -0,0 +1,175 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    errors::{RuntimeError, RuntimeResult},
+    gc::{self, Edge, Trace},
+    interpreter::Interpreter,
+    native_method::{MethodFn, NativeMethod},
+    number::exact_int,
+    tokens::token::{LoxType, Token},
+};
+
+pub type LoxMap = Rc<RefCell<MapStore>>;
+
+/// The hashable form of a map key.
+///
+/// Only strings, numbers, booleans and nil can be keys. Two keys are the same
+/// when they have the same type and value, so `1` and `"1"` are distinct,
+/// `1` and `1.0` are the same key, as are `0` and `-0`, and `nil` equals
+/// `nil`. NaN is rejected because it is not equal to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    String(String),
+    Int(i64),
+    /// Bits of a float without an integer value.
+    Float(u64),
+    Bool(bool),
+    Nil,
+}
+
+impl TryFrom<&LoxType> for MapKey {
+    type Error = String;
+
+    fn try_from(value: &LoxType) -> Result<Self, Self::Error> {
+        Ok(match value {
+            LoxType::String(s) => MapKey::String(s.clone()),
+            LoxType::Int(i) => MapKey::Int(*i),
+            LoxType::Float(n) if n.is_nan() => return Err("NaN cannot be a map key.".into()),
+            LoxType::Float(n) => match exact_int(*n) {
+                Some(i) => MapKey::Int(i),
+                None => MapKey::Float(n.to_bits()),
+            },
+            LoxType::True => MapKey::Bool(true),
+            LoxType::False => MapKey::Bool(false),
+            LoxType::Nil => MapKey::Nil,
+            other => return Err(format!("{other} cannot be a map key.")),
+        })
+    }
+}
+
+/// Map entries in insertion order, with a hash index over the keys.
+#[derive(Debug, Default)]
+pub struct MapStore {
+    entries: Vec<(LoxType, LoxType)>,
+    index: HashMap<MapKey, usize>,
+}
+
+impl MapStore {
+    pub fn get(&self, key: &LoxType) -> Result<Option<&LoxType>, String> {
+        let key = MapKey::try_from(key)?;
+        Ok(self.index.get(&key).map(|&i| &self.entries[i].1))
+    }
+
+    pub fn insert(&mut self, key: LoxType, value: LoxType) -> Result<(), String> {
+        match self.index.get(&MapKey::try_from(&key)?) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index
+                    .insert(MapKey::try_from(&key)?, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &LoxType) -> Result<Option<LoxType>, String> {
+        let Some(i) = self.index.remove(&MapKey::try_from(key)?) else {
+            return Ok(None);
+        };
+        let (_, value) = self.entries.remove(i);
+        for pos in self.index.values_mut() {
+            if *pos > i {
+                *pos -= 1;
+            }
+        }
+        Ok(Some(value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = &(LoxType, LoxType)> {
+        self.entries.iter()
+    }
+}
+
+const METHODS: &[(&str, usize, MethodFn)] = &[
+    ("keys", 0, keys),
+    ("values", 0, values),
+    ("has", 1, has),
+    ("delete", 1, delete),
+    ("len", 0, len),
+];
+
+/// Look up a built-in method on `map`, bound to it.
+pub fn get_method(map: &LoxMap, name: &Token) -> RuntimeResult<LoxType> {
+    match METHODS.iter().find(|(n, ..)| *n == name.lexeme) {
+        Some(&(n, arity, fun)) => {
+            Ok(NativeMethod::new(n, arity, LoxType::Map(Rc::clone(map)), fun).into())
+        }
+        None => Err(RuntimeError::at(
+            format!("Undefined property '{}'.", name.lexeme),
+            name.span,
+        )),
+    }
+}
+
+fn this(receiver: &LoxType) -> &LoxMap {
+    match receiver {
+        LoxType::Map(map) => map,
+        _ => unreachable!("map method bound to a non map"),
+    }
+}
+
+fn keys(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let keys = this(receiver)
+        .borrow()
+        .iter()
+        .map(|(k, _)| k.clone())
+        .collect();
+    Ok(LoxType::List(gc::alloc(keys)))
+}
+
+fn values(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let values = this(receiver)
+        .borrow()
+        .iter()
+        .map(|(_, v)| v.clone())
+        .collect();
+    Ok(LoxType::List(gc::alloc(values)))
+}
+
+fn has(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let map = this(receiver).borrow();
+    let found = map.get(&args[0]).map_err(|e| RuntimeError::new(e, 0))?;
+    Ok(found.is_some().into())
+}
+
+fn delete(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let mut map = this(receiver).borrow_mut();
+    let removed = map.remove(&args[0]).map_err(|e| RuntimeError::new(e, 0))?;
+    Ok(removed.is_some().into())
+}
+
+fn len(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).borrow().len().into())
+}
+
+impl Trace for MapStore {
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        for (key, value) in &self.entries {
+            visit(Edge::Value(key));
+            visit(Edge::Value(value));
+        }
+    }
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    f64::consts,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    environment::Environment,
+    errors::{RuntimeError, RuntimeResult},
+    gc, gen_native_func,
+    interpreter::Interpreter,
+    module::LoxModule,
+    number::{exact_int, Number},
+    tokens::token::{ref_cell, LoxCallable, LoxCallableType, LoxType},
+};
+
+type UnaryFn = fn(f64) -> f64;
+
+/// A function from a float to a float, such as `math.sqrt`.
+#[derive(Debug)]
+struct FloatFn {
+    name: &'static str,
+    fun: UnaryFn,
+}
+
+impl LoxCallable for FloatFn {
+    fn kind(&self) -> LoxCallableType {
+        LoxCallableType::NativeFunction
+    }
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+    fn arity(&self) -> usize {
+        1
+    }
+    fn call(&self, _: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+        Ok((self.fun)(float_arg(self.name, &args[0])?).into())
+    }
+}
+
+const FLOAT_FNS: &[(&str, UnaryFn)] = &[
+    ("sqrt", f64::sqrt),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+    ("asin", f64::asin),
+    ("acos", f64::acos),
+    ("atan", f64::atan),
+    ("exp", f64::exp),
+    ("log", f64::ln),
+    ("log2", f64::log2),
+    ("log10", f64::log10),
+];
+
+fn number_arg(name: &str, arg: &LoxType) -> RuntimeResult<Number> {
+    arg.as_number().ok_or_else(|| {
+        RuntimeError::new(format!("{name}() expects a number, got {}.", arg.repr()), 0)
+    })
+}
+
+fn float_arg(name: &str, arg: &LoxType) -> RuntimeResult<f64> {
+    number_arg(name, arg).map(Number::as_f64)
+}
+
+/// Apply `f` to a float argument. Integers are returned unchanged, since
+/// they are already whole.
+fn round_with(name: &str, arg: &LoxType, f: UnaryFn) -> RuntimeResult<LoxType> {
+    Ok(match number_arg(name, arg)? {
+        Number::Int(i) => LoxType::Int(i),
+        Number::Float(x) => LoxType::Float(f(x)),
+    })
+}
+
+gen_native_func!(Floor as "floor", i, round_with("floor", x, f64::floor)?, x);
+gen_native_func!(Ceil as "ceil", i, round_with("ceil", x, f64::ceil)?, x);
+gen_native_func!(Round as "round", i, round_with("round", x, f64::round)?, x);
+
+gen_native_func!(
+    Abs as "abs",
+    i,
+    match number_arg("abs", x)? {
+        Number::Int(i) => i
+            .checked_abs()
+            .ok_or_else(|| RuntimeError::new("Integer overflow.", 0))?
+            .into(),
+        Number::Float(f) => f.abs().into(),
+    },
+    x
+);
+
+gen_native_func!(
+    Pow as "pow",
+    i,
+    match (number_arg("pow", base)?, number_arg("pow", exponent)?) {
+        (Number::Int(b), Number::Int(e)) if e >= 0 => {
+            let e = u32::try_from(e).ok();
+            e.and_then(|e| b.checked_pow(e))
+                .ok_or_else(|| RuntimeError::new("Integer overflow.", 0))?
+                .into()
+        }
+        (b, e) => b.as_f64().powf(e.as_f64()).into(),
+    },
+    base,
+    exponent
+);
+
+gen_native_func!(
+    Atan2 as "atan2",
+    i,
+    float_arg("atan2", y)?.atan2(float_arg("atan2", x)?).into(),
+    y,
+    x
+);
+
+/// The smaller or larger of two numbers, as selected by `pick`. NaN wins
+/// over everything.
+fn min_max(name: &str, a: &LoxType, b: &LoxType, pick: Ordering) -> RuntimeResult<LoxType> {
+    let (x, y) = (number_arg(name, a)?, number_arg(name, b)?);
+    Ok(match x.compare(y) {
+        Some(order) if order == pick || order == Ordering::Equal => a.clone(),
+        Some(_) => b.clone(),
+        None if x.as_f64().is_nan() => a.clone(),
+        None => b.clone(),
+    })
+}
+
+gen_native_func!(Min as "min", i, min_max("min", a, b, Ordering::Less)?, a, b);
+gen_native_func!(Max as "max", i, min_max("max", a, b, Ordering::Greater)?, a, b);
+
+gen_native_func!(
+    IsNan as "is_nan",
+    i,
+    float_arg("is_nan", x)?.is_nan().into(),
+    x
+);
+gen_native_func!(
+    IsInf as "is_inf",
+    i,
+    float_arg("is_inf", x)?.is_infinite().into(),
+    x
+);
+
+thread_local! {
+    /// State of the xorshift generator behind `math.random()`. Never zero.
+    static RANDOM: Cell<u64> = Cell::new(time_seed());
+}
+
+fn time_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    scramble(nanos)
+}
+
+/// Spread the bits of a seed (splitmix64), so that similar seeds start far
+/// apart and the state is never zero.
+fn scramble(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    match z ^ (z >> 31) {
+        0 => 1,
+        z => z,
+    }
+}
+
+gen_native_func!(Random as "random", i, {
+    // xorshift64*, using the top 53 bits for the float
+    let mut x = RANDOM.get();
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    RANDOM.set(x);
+    let bits = x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+    LoxType::Float(bits as f64 / (1u64 << 53) as f64)
+});
+
+gen_native_func!(
+    Seed as "seed",
+    i,
+    {
+        let seed = match seed {
+            LoxType::Int(i) => *i,
+            LoxType::Float(f) => exact_int(*f).ok_or_else(|| {
+                RuntimeError::new(format!("seed() expects an integer, got {f:?}."), 0)
+            })?,
+            _ => {
+                return Err(RuntimeError::new(
+                    format!("seed() expects an integer, got {}.", seed.repr()),
+                    0,
+                ))
+            }
+        };
+        RANDOM.set(scramble(seed as u64));
+        LoxType::Nil
+    },
+    seed
+);
+
+/// The built-in `math` module.
+pub fn math_module() -> LoxType {
+    let mut env = Environment::new(None);
+    for &(name, fun) in FLOAT_FNS {
+        env.define(name, LoxType::Callable(ref_cell(FloatFn { name, fun })));
+    }
+    let natives: [(&str, LoxType); 12] = [
+        ("floor", Floor.into()),
+        ("ceil", Ceil.into()),
+        ("round", Round.into()),
+        ("abs", Abs.into()),
+        ("pow", Pow.into()),
+        ("atan2", Atan2.into()),
+        ("min", Min.into()),
+        ("max", Max.into()),
+        ("is_nan", IsNan.into()),
+        ("is_inf", IsInf.into()),
+        ("random", Random.into()),
+        ("seed", Seed.into()),
+    ];
+    for (name, native) in natives {
+        env.define(name, native);
+    }
+    env.define("pi", consts::PI.into());
+    env.define("e", consts::E.into());
+    env.define("inf", f64::INFINITY.into());
+    env.define("nan", f64::NAN.into());
+    LoxType::Module(Rc::new(LoxModule {
+        name: "math".into(),
+        path: Default::default(),
+        globals: gc::alloc(env),
+    }))
+}
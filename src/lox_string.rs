@@ -0,0 +1,239 @@
+use crate::{
+    errors::{RuntimeError, RuntimeResult},
+    gc, gen_native_func,
+    interpreter::Interpreter,
+    native_method::{MethodFn, NativeMethod},
+    number::{exact_int, Number},
+    tokens::token::{ref_cell, LoxCallable, LoxCallableType, LoxType, Token},
+};
+
+/// Methods of string values. Positions and lengths count characters, not
+/// bytes.
+const METHODS: &[(&str, usize, MethodFn)] = &[
+    ("len", 0, len),
+    ("substring", 2, substring),
+    ("find", 1, find),
+    ("split", 1, split),
+    ("join", 1, join),
+    ("trim", 0, trim),
+    ("upper", 0, upper),
+    ("lower", 0, lower),
+    ("replace", 2, replace),
+    ("starts_with", 1, starts_with),
+    ("ends_with", 1, ends_with),
+    ("repeat", 1, repeat),
+    ("code_at", 1, code_at),
+];
+
+/// Look up a built-in method on `s`, bound to it.
+pub fn get_method(s: &str, name: &Token) -> RuntimeResult<LoxType> {
+    match METHODS.iter().find(|(n, ..)| *n == name.lexeme) {
+        Some(&(n, arity, fun)) => Ok(NativeMethod::new(n, arity, s.into(), fun).into()),
+        None => Err(RuntimeError::at(
+            format!("Undefined property '{}'.", name.lexeme),
+            name.span,
+        )),
+    }
+}
+
+fn error<T: Into<String>>(message: T) -> RuntimeError {
+    RuntimeError::new(message, 0)
+}
+
+fn this(receiver: &LoxType) -> &str {
+    match receiver {
+        LoxType::String(s) => s,
+        _ => unreachable!("string method bound to a non string"),
+    }
+}
+
+fn string_arg<'a>(method: &str, arg: &'a LoxType) -> RuntimeResult<&'a str> {
+    match arg {
+        LoxType::String(s) => Ok(s),
+        _ => Err(error(format!(
+            "{method}() expects a string, got {}.",
+            arg.repr()
+        ))),
+    }
+}
+
+fn int_arg(method: &str, arg: &LoxType) -> RuntimeResult<i64> {
+    match arg.as_number().and_then(|n| match n {
+        Number::Int(i) => Some(i),
+        Number::Float(f) => exact_int(f),
+    }) {
+        Some(i) => Ok(i),
+        None => Err(error(format!(
+            "{method}() expects an integer, got {}.",
+            arg.repr()
+        ))),
+    }
+}
+
+/// Convert `index` into a character position in a string of `len`
+/// characters. With `inclusive` the position one past the end is accepted
+/// as well.
+fn to_index(method: &str, index: &LoxType, len: usize, inclusive: bool) -> RuntimeResult<usize> {
+    let n = int_arg(method, index)?;
+    match usize::try_from(n) {
+        Ok(n) if n < len || (inclusive && n == len) => Ok(n),
+        _ => Err(error(format!(
+            "String index {n} out of range for length {len}."
+        ))),
+    }
+}
+
+/// Byte offset of the character at position `index`.
+fn byte_offset(s: &str, index: usize) -> usize {
+    s.char_indices().nth(index).map_or(s.len(), |(i, _)| i)
+}
+
+fn len(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).chars().count().into())
+}
+
+fn substring(
+    _: &mut Interpreter,
+    receiver: &LoxType,
+    args: Vec<LoxType>,
+) -> RuntimeResult<LoxType> {
+    let s = this(receiver);
+    let len = s.chars().count();
+    let start = to_index("substring", &args[0], len, true)?;
+    let end = match &args[1] {
+        LoxType::Nil => len,
+        end => to_index("substring", end, len, true)?,
+    };
+    if start > end {
+        return Err(error(format!(
+            "Substring start {start} is after its end {end}."
+        )));
+    }
+    Ok(s[byte_offset(s, start)..byte_offset(s, end)].into())
+}
+
+fn find(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let s = this(receiver);
+    let needle = string_arg("find", &args[0])?;
+    Ok(match s.find(needle) {
+        Some(at) => s[..at].chars().count().into(),
+        None => LoxType::Nil,
+    })
+}
+
+fn split(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let s = this(receiver);
+    let parts = match string_arg("split", &args[0])? {
+        "" => s.chars().map(|c| c.to_string().into()).collect(),
+        sep => s.split(sep).map(LoxType::from).collect(),
+    };
+    Ok(LoxType::List(gc::alloc(parts)))
+}
+
+fn join(i: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let LoxType::List(list) = &args[0] else {
+        return Err(error(format!(
+            "join() expects a list, got {}.",
+            args[0].repr()
+        )));
+    };
+    let parts = list
+        .borrow()
+        .iter()
+        .map(|item| i.stringify(item))
+        .collect::<Vec<_>>();
+    Ok(parts.join(this(receiver)).into())
+}
+
+fn trim(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).trim().into())
+}
+
+fn upper(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).to_uppercase().into())
+}
+
+fn lower(_: &mut Interpreter, receiver: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    Ok(this(receiver).to_lowercase().into())
+}
+
+fn replace(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let from = string_arg("replace", &args[0])?;
+    let to = string_arg("replace", &args[1])?;
+    if from.is_empty() {
+        return Err(error("replace() cannot replace an empty string."));
+    }
+    Ok(this(receiver).replace(from, to).into())
+}
+
+fn starts_with(
+    _: &mut Interpreter,
+    receiver: &LoxType,
+    args: Vec<LoxType>,
+) -> RuntimeResult<LoxType> {
+    let prefix = string_arg("starts_with", &args[0])?;
+    Ok(this(receiver).starts_with(prefix).into())
+}
+
+fn ends_with(
+    _: &mut Interpreter,
+    receiver: &LoxType,
+    args: Vec<LoxType>,
+) -> RuntimeResult<LoxType> {
+    let suffix = string_arg("ends_with", &args[0])?;
+    Ok(this(receiver).ends_with(suffix).into())
+}
+
+fn repeat(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let Ok(count) = usize::try_from(int_arg("repeat", &args[0])?) else {
+        return Err(error("repeat() expects a non-negative count."));
+    };
+    Ok(this(receiver).repeat(count).into())
+}
+
+fn code_at(_: &mut Interpreter, receiver: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let s = this(receiver);
+    let index = to_index("code_at", &args[0], s.chars().count(), false)?;
+    let c = s.chars().nth(index).expect("index checked");
+    Ok(LoxType::Int(c as i64))
+}
+
+gen_native_func!(
+    Num as "num",
+    i,
+    {
+        let s = match value {
+            LoxType::String(s) => s.trim(),
+            LoxType::Int(_) | LoxType::Float(_) => return Ok(value.clone()),
+            _ => return Err(error(format!("num() expects a string, got {}.", value.repr()))),
+        };
+        let invalid = || error(format!("Cannot convert {s:?} to a number."));
+        // `inf` and `nan` are not number literals
+        if s.chars().any(|c| c.is_alphabetic() && c != 'e' && c != 'E') {
+            return Err(invalid());
+        }
+        match s.parse::<i64>() {
+            Ok(n) => LoxType::Int(n),
+            Err(_) => LoxType::Float(s.parse().map_err(|_| invalid())?),
+        }
+    },
+    value
+);
+
+gen_native_func!(
+    Chr as "chr",
+    i,
+    {
+        let code = int_arg("chr", code)?;
+        match u32::try_from(code).ok().and_then(char::from_u32) {
+            Some(c) => c.to_string().into(),
+            None => return Err(error(format!("Invalid character code {code}."))),
+        }
+    },
+    code
+);
+
+/// The global string functions besides `str`, by name.
+pub fn natives() -> [(&'static str, LoxType); 2] {
+    [("num", Num.into()), ("chr", Chr.into())]
+}
@@ -1,27 +1,43 @@
-mod ast_printer;
-mod errors;
-mod globals;
-mod interpreter;
-mod lox_class;
-mod lox_function;
-mod parser;
 mod program;
-mod resolver;
-mod scanner;
-mod tokens;
-use std::{env, io};
-mod environment;
-mod syntax;
+use lox::Backend;
 use program::Program;
+use std::{env, io, process, thread};
+
+const USAGE: &str = "usage: lox [--backend=tree|vm] [--nil-is-truthy] [file]";
+
+/// The tree walker recurses on the native stack for every script call, so
+/// give it enough room to reach `MAX_CALL_DEPTH` even in debug builds.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() -> io::Result<()> {
-    let p = Program::new();
-    match env::args().len() {
-        1 => p.repl(),
-        2 => p.run_script(env::args().nth(1).unwrap().to_string()),
-        _ => Err(io::Error::new(
-            io::ErrorKind::Unsupported,
-            "usage: lox [file]",
-        )),
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)?
+        .join()
+        // the panic message has already been printed by the thread
+        .unwrap_or_else(|_| process::exit(101))
+}
+
+fn run() -> io::Result<()> {
+    let mut backend = Backend::TreeWalker;
+    let mut nil_is_truthy = false;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        if arg == "--nil-is-truthy" {
+            nil_is_truthy = true;
+            continue;
+        }
+        match arg.strip_prefix("--backend=") {
+            Some("tree") => backend = Backend::TreeWalker,
+            Some("vm") => backend = Backend::Vm,
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+            None => files.push(arg),
+        }
+    }
+    let p = Program::new(backend, nil_is_truthy, STACK_SIZE);
+    match files.len() {
+        0 => p.repl(),
+        1 => p.run_script(files.remove(0)),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, USAGE)),
     }
 }
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{environment::EnclosingEnv, errors::Diagnostic};
+
+/// Environment variable listing extra directories to search for modules,
+/// separated the same way as `PATH`.
+pub const SEARCH_PATH_VAR: &str = "LOX_PATH";
+
+/// An imported module: the global environment its top level ran in.
+#[derive(Debug)]
+pub struct LoxModule {
+    pub name: String,
+    /// Empty for built-in modules such as `math`.
+    pub path: PathBuf,
+    pub globals: EnclosingEnv,
+}
+
+/// Finds, caches and tracks the modules loaded by an interpreter.
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    cache: HashMap<PathBuf, Rc<LoxModule>>,
+    /// Modules whose top level is currently running, outermost first.
+    loading: Vec<PathBuf>,
+    /// Files of imported modules. Spans in a module use its index plus one as
+    /// their source id.
+    sources: Vec<PathBuf>,
+    /// File the main program was read from, if any. Imports of the main
+    /// program are relative to its directory, or to the current directory
+    /// otherwise.
+    script: Option<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn set_script(&mut self, path: &Path) {
+        self.script = Some(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    }
+
+    /// Directory the module currently being loaded lives in.
+    fn importer_dir(&self) -> PathBuf {
+        self.script
+            .iter()
+            .chain(&self.loading)
+            .last()
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    /// Find the file `name` refers to. Paths starting with `.` are relative
+    /// to the importing file only; other paths are also looked up in the
+    /// directories of [`SEARCH_PATH_VAR`] and the current directory. The
+    /// `.lox` extension may be left out.
+    pub fn find(&self, name: &str) -> Result<PathBuf, String> {
+        let importer = self.importer_dir();
+        let mut dirs = vec![importer];
+        if !name.starts_with('.') && !Path::new(name).is_absolute() {
+            if let Some(paths) = env::var_os(SEARCH_PATH_VAR) {
+                dirs.extend(env::split_paths(&paths));
+            }
+            dirs.push(PathBuf::new());
+        }
+        for dir in dirs {
+            let path = dir.join(name);
+            let candidates = [path.with_extension("lox"), path];
+            let candidates = match Path::new(name).extension() {
+                Some(_) => &candidates[1..],
+                None => &candidates[..],
+            };
+            for candidate in candidates {
+                if candidate.is_file() {
+                    return candidate.canonicalize().map_err(|e| e.to_string());
+                }
+            }
+        }
+        Err(format!("Module \"{name}\" not found."))
+    }
+
+    pub fn get(&self, path: &Path) -> Option<Rc<LoxModule>> {
+        self.cache.get(path).cloned()
+    }
+
+    /// Mark `path` as loading and return the source id for its spans, or
+    /// describe the import cycle it would close.
+    pub fn begin(&mut self, path: PathBuf) -> Result<u32, String> {
+        let chain = self.script.iter().chain(&self.loading);
+        if let Some(start) = chain.clone().position(|p| *p == path) {
+            let cycle = chain
+                .skip(start)
+                .chain([&path])
+                .map(|p| display_name(p))
+                .collect::<Vec<_>>();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+        self.loading.push(path.clone());
+        self.sources.push(path);
+        Ok(self.sources.len() as u32)
+    }
+
+    /// Finish loading the innermost module, caching it if it ran cleanly.
+    pub fn finish(&mut self, module: Option<Rc<LoxModule>>) {
+        let path = self.loading.pop().expect("finish without begin");
+        if let Some(module) = module {
+            self.cache.insert(path, module);
+        }
+    }
+
+    /// The file spans with the given source id point into.
+    pub fn source_path(&self, source: u32) -> Option<&Path> {
+        let index = (source as usize).checked_sub(1)?;
+        self.sources.get(index).map(PathBuf::as_path)
+    }
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Module name derived from its file, used when printing the module.
+pub fn module_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The error for a module `name` whose source in `file` did not compile.
+pub fn load_error(name: &str, file: &Path, errs: Vec<Diagnostic>) -> String {
+    let errs = errs
+        .into_iter()
+        .map(|mut err| {
+            err.file = Some(file.display().to_string());
+            err.to_string()
+        })
+        .collect::<Vec<_>>();
+    format!("Could not load module \"{name}\":\n{}", errs.join("\n"))
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use crate::{
+    lox_class::LoxClass,
+    native_method::{MethodFn, NativeMethodDef},
+    tokens::token::LoxType,
+};
+
+/// Builder for a class implemented in Rust, such as `File`.
+///
+/// Methods receive the instance they are called on as their receiver. A
+/// constructor declared with [`init`](Self::init) runs on every new
+/// instance, and usually attaches the typed state the other methods work on
+/// with [`LoxInstance::set_native`](crate::lox_class::LoxInstance::set_native).
+/// Scripts can subclass native classes and call `super.init(...)`.
+pub struct NativeClass {
+    class: LoxClass,
+}
+
+impl NativeClass {
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Self {
+            class: LoxClass::new(name, HashMap::new(), None),
+        }
+    }
+
+    /// Run `init` with the new instance and `arity` arguments whenever the
+    /// class is called.
+    pub fn init(self, arity: usize, init: MethodFn) -> Self {
+        self.method("init", arity, init)
+    }
+
+    pub fn method(mut self, name: &'static str, arity: usize, fun: MethodFn) -> Self {
+        self.class
+            .define_native_method(NativeMethodDef { name, arity, fun });
+        self
+    }
+
+    /// Define a member of the class itself, such as `File.exists`.
+    pub fn static_member<T: Into<String>>(self, name: T, value: LoxType) -> Self {
+        self.class.define_static(name, value);
+        self
+    }
+
+    pub fn build(self) -> LoxClass {
+        self.class
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{
+    errors::RuntimeResult,
+    gc::{self, Edge},
+    interpreter::Interpreter,
+    tokens::token::{LoxCallable, LoxCallableType, LoxType},
+};
+
+pub type MethodFn = fn(&mut Interpreter, &LoxType, Vec<LoxType>) -> RuntimeResult<LoxType>;
+
+/// A method implemented in Rust and bound to a built-in value such as a
+/// list, so that `xs.push` can be passed around like any other function.
+#[derive(Debug)]
+pub struct NativeMethod {
+    name: &'static str,
+    arity: usize,
+    receiver: LoxType,
+    fun: MethodFn,
+}
+
+impl NativeMethod {
+    pub fn new(name: &'static str, arity: usize, receiver: LoxType, fun: MethodFn) -> Self {
+        Self {
+            name,
+            arity,
+            receiver,
+            fun,
+        }
+    }
+}
+
+impl LoxCallable for NativeMethod {
+    fn kind(&self) -> LoxCallableType {
+        LoxCallableType::NativeFunction
+    }
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+    fn arity(&self) -> usize {
+        self.arity
+    }
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+        (self.fun)(interpreter, &self.receiver, args)
+    }
+    fn trace(&self, visit: &mut dyn FnMut(Edge<'_>)) {
+        visit(Edge::Value(&self.receiver));
+    }
+}
+
+/// A method of a native class, bound to an instance when it is looked up.
+#[derive(Debug, Clone, Copy)]
+pub struct NativeMethodDef {
+    pub name: &'static str,
+    pub arity: usize,
+    pub fun: MethodFn,
+}
+
+impl NativeMethodDef {
+    pub fn bind(&self, receiver: LoxType) -> NativeMethod {
+        NativeMethod::new(self.name, self.arity, receiver, self.fun)
+    }
+}
+
+impl From<NativeMethod> for LoxType {
+    fn from(value: NativeMethod) -> Self {
+        gc::alloc_callable(value)
+    }
+}
@@ -0,0 +1,200 @@
+//! Arithmetic shared by the tree-walker and the VM.
+//!
+//! Integers are `i64` and every operation on them is checked for overflow.
+//! An operation between an integer and a float promotes the integer, `/`
+//! always divides as floats, and `~/` is floor division that always produces
+//! an integer. `%` takes the sign of the divisor, so that
+//! `a == (a ~/ b) * b + a % b`. The bitwise operators only accept integers.
+
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::tokens::token_type::TokenType;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    IntDiv,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+/// The arithmetic or bitwise operation a binary operator token stands for.
+pub fn num_op(ty: TokenType) -> NumOp {
+    match ty {
+        TokenType::Plus => NumOp::Add,
+        TokenType::Minus => NumOp::Sub,
+        TokenType::Star => NumOp::Mul,
+        TokenType::Slash => NumOp::Div,
+        TokenType::TildeSlash => NumOp::IntDiv,
+        TokenType::Percent => NumOp::Rem,
+        TokenType::Ampersand => NumOp::BitAnd,
+        TokenType::Pipe => NumOp::BitOr,
+        TokenType::Caret => NumOp::BitXor,
+        TokenType::LessLess => NumOp::Shl,
+        TokenType::GreaterGreater => NumOp::Shr,
+        _ => panic!("{ty:?} is not an arithmetic operator"),
+    }
+}
+
+pub type NumResult = Result<Number, String>;
+
+fn overflow() -> String {
+    "Integer overflow.".into()
+}
+
+fn int_op(op: NumOp, a: i64, b: i64) -> NumResult {
+    use NumOp::*;
+    if matches!(op, IntDiv | Rem) && b == 0 {
+        return Err("Division by zero.".into());
+    }
+    let res = match op {
+        Add => a.checked_add(b),
+        Sub => a.checked_sub(b),
+        Mul => a.checked_mul(b),
+        Div => return Ok(Number::Float(a as f64 / b as f64)),
+        IntDiv => a.checked_div(b).map(|q| match a % b {
+            r if r != 0 && (r < 0) != (b < 0) => q - 1,
+            _ => q,
+        }),
+        // only `i64::MIN % -1` wraps, and its result 0 is exact
+        Rem => Some(match a.wrapping_rem(b) {
+            r if r != 0 && (r < 0) != (b < 0) => r + b,
+            r => r,
+        }),
+        BitAnd => Some(a & b),
+        BitOr => Some(a | b),
+        BitXor => Some(a ^ b),
+        Shl | Shr => {
+            let Ok(shift @ 0..=63) = u32::try_from(b) else {
+                return Err("Shift amount must be between 0 and 63.".into());
+            };
+            Some(if op == Shl { a << shift } else { a >> shift })
+        }
+    };
+    res.map(Number::Int).ok_or_else(overflow)
+}
+
+fn float_op(op: NumOp, a: f64, b: f64) -> NumResult {
+    use NumOp::*;
+    let res = match op {
+        Add => a + b,
+        Sub => a - b,
+        Mul => a * b,
+        Div => a / b,
+        IntDiv => {
+            if b == 0.0 {
+                return Err("Division by zero.".into());
+            }
+            return float_to_int((a / b).floor());
+        }
+        Rem => match a % b {
+            r if r != 0.0 && (r < 0.0) != (b < 0.0) => r + b,
+            r => r,
+        },
+        BitAnd | BitOr | BitXor | Shl | Shr => {
+            return Err("Bitwise operands must be integers.".into())
+        }
+    };
+    Ok(Number::Float(res))
+}
+
+fn float_to_int(f: f64) -> NumResult {
+    int_in_range(f).map(Number::Int).ok_or_else(overflow)
+}
+
+/// Truncate `f` to an integer, if it is within `i64` range.
+fn int_in_range(f: f64) -> Option<i64> {
+    // every f64 in this range converts exactly, the bounds are -2^63 and 2^63
+    let bound = -(i64::MIN as f64);
+    (f.is_finite() && (-bound..bound).contains(&f)).then_some(f as i64)
+}
+
+/// The integer equal to `f`, if there is one.
+pub fn exact_int(f: f64) -> Option<i64> {
+    if f.fract() == 0.0 {
+        int_in_range(f)
+    } else {
+        None
+    }
+}
+
+impl Number {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    pub fn binary(self, op: NumOp, rhs: Number) -> NumResult {
+        match (self, rhs) {
+            (Number::Int(a), Number::Int(b)) => int_op(op, a, b),
+            (a, b) => float_op(op, a.as_f64(), b.as_f64()),
+        }
+    }
+
+    pub fn negate(self) -> NumResult {
+        match self {
+            Number::Int(i) => i.checked_neg().map(Number::Int).ok_or_else(overflow),
+            Number::Float(f) => Ok(Number::Float(-f)),
+        }
+    }
+
+    pub fn bit_not(self) -> NumResult {
+        match self {
+            Number::Int(i) => Ok(Number::Int(!i)),
+            Number::Float(_) => Err("Bitwise operands must be integers.".into()),
+        }
+    }
+
+    /// Compare by value, so that `1 == 1.0`.
+    pub fn compare(self, other: Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+            (Number::Int(a), Number::Float(b)) => cmp_int_float(a, b),
+            (Number::Float(a), Number::Int(b)) => cmp_int_float(b, a).map(Ordering::reverse),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        }
+    }
+}
+
+/// Exact comparison, even for integers that have no exact `f64`.
+fn cmp_int_float(a: i64, b: f64) -> Option<Ordering> {
+    if b.is_nan() {
+        return None;
+    }
+    match int_in_range(b.trunc()) {
+        Some(t) => Some(a.cmp(&t).then(0.0.partial_cmp(&(b - b.trunc()))?)),
+        None => 0.0.partial_cmp(&b),
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(*other) == Some(Ordering::Equal)
+    }
+}
+
+/// Integers print without a decimal point and floats always with one (or
+/// an exponent), so `3` and `3.0` can be told apart.
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(i) => write!(f, "{i}"),
+            Number::Float(n) => write!(f, "{n:?}"),
+        }
+    }
+}
@@ -1,14 +1,20 @@
+use std::rc::Rc;
+
 use crate::{
     errors::{ParseError, ParseResult},
+    globals::stringify_fn,
     syntax::{
         expr::{
-            self, Assign, Binary, Call, Expr, Get, Grouping, Literal, Logical, Set, Super, Unary,
-            Variable,
+            self, Assign, Binary, Call, Expr, Get, Grouping, Index, Lambda, List, Literal, Logical,
+            Map, Set, SetIndex, Super, Unary, Variable,
+        },
+        stmt::{
+            self, Block, Class, Expression, Function, If, MethodKind, Print, Return, Stmt, Var,
+            While,
         },
-        stmt::{Block, Class, Expression, Function, If, Print, Return, Stmt, Var, While},
     },
     tokens::{
-        token::{LoxCallableType, LoxType, Token},
+        token::{LoxCallableType, LoxType, Span, Token},
         token_type::TokenType,
     },
 };
@@ -44,10 +50,14 @@ impl Parser {
     fn declaration(&mut self) -> ParseResult<Stmt> {
         use TokenType::{Class, Fun, Var};
 
+        let start = self.start();
         let res = if check!(self.peek(), Class) {
             self.advance();
             self.class_declaration()
-        } else if check!(self.peek(), Fun) {
+        } else if check!(self.peek(), Fun)
+            && !check!(self.tokens.get(self.current + 1), TokenType::LeftParen)
+        {
+            // `fun (` starts a lambda expression instead
             self.advance();
             self.function(LoxCallableType::Function)
         } else {
@@ -59,10 +69,8 @@ impl Parser {
             }
         };
 
-        res.or_else(|err| {
-            self.synchronize();
-            Err(err)
-        })
+        res.map(|stmt| stmt.with_span(self.span_from(start)))
+            .inspect_err(|_| self.synchronize())
     }
     fn class_declaration(&mut self) -> ParseResult<Stmt> {
         use TokenType::{Identifier, LeftBrace, Less, RightBrace};
@@ -73,39 +81,81 @@ impl Parser {
             let sc = self
                 .consume(Identifier, "Expected superclass class name")?
                 .clone();
-            Some(expr::Variable::new(sc, None))
+            let span = sc.span;
+            Some(expr::Variable::new(sc, None).with_span(span))
         } else {
             None
         };
 
         self.consume(LeftBrace, "Expected '{' before class body")?;
         let mut methods = Vec::new();
+        let mut class_methods = Vec::new();
         while !self.is_at_end() && !check!(self.peek(), RightBrace) {
+            let is_class_method = check!(self.peek(), TokenType::Class);
+            if is_class_method {
+                self.advance();
+            }
             let fun = self.function(LoxCallableType::Class)?;
             match fun {
-                Stmt::Function(fun) => {
-                    methods.push(fun);
+                Stmt::Function(fun) if is_class_method => {
+                    if fun.method_kind != MethodKind::Method {
+                        return Err(ParseError::at(
+                            "Class methods cannot be getters or setters.",
+                            fun.name.span,
+                        ));
+                    }
+                    class_methods.push(fun)
                 }
+                Stmt::Function(fun) => methods.push(fun),
                 _ => panic!("Unexpected statement in class body"),
             }
         }
         self.advance();
-        return Ok(Class::new(name, superclass, methods).into());
+        return Ok(Class::new(name, superclass, methods, class_methods).into());
     }
     fn function(&mut self, kind: LoxCallableType) -> ParseResult<Stmt> {
-        use TokenType::{Comma, Identifier, LeftBrace, LeftParen, RightParen};
+        use TokenType::{Equal, Identifier, LeftBrace, LeftParen};
 
         let name = self
             .consume(Identifier, &format!("Expected {:?} name.", kind))?
             .clone();
-        self.consume(LeftParen, &format!("Expected '(' after {:?} name.", kind))?;
+        // in a class body, `name { }` declares a getter and `name=(v) { }`
+        // a setter
+        let method_kind = match kind {
+            LoxCallableType::Class if check!(self.peek(), LeftBrace) => MethodKind::Getter,
+            LoxCallableType::Class if check!(self.peek(), Equal) => {
+                self.advance();
+                MethodKind::Setter
+            }
+            _ => MethodKind::Method,
+        };
+        let mut params = Vec::new();
+        if method_kind != MethodKind::Getter {
+            self.consume(LeftParen, &format!("Expected '(' after {:?} name.", kind))?;
+            params = self.parameters()?;
+        }
+        if method_kind == MethodKind::Setter && params.len() != 1 {
+            return Err(ParseError::at(
+                "A setter must have exactly one parameter.",
+                name.span,
+            ));
+        }
+        self.consume(LeftBrace, &format!("Expected '{{' before {:?} body", kind))?;
+        let body = self.block()?;
+        return Ok(Function::new(name, params, body, method_kind).into());
+    }
+    /// Parse a parameter list after its opening parenthesis, up to and
+    /// including the closing one.
+    fn parameters(&mut self) -> ParseResult<Vec<Token>> {
+        use TokenType::{Comma, Identifier, RightParen};
+
         let mut params = Vec::new();
         if !check!(self.peek(), RightParen) {
             loop {
                 if params.len() >= 255 {
-                    return Err(ParseError::new(
+                    return Err(ParseError::at(
                         "Can't have more than 255 params",
-                        self.peek().unwrap().line,
+                        self.peek().unwrap().span,
                     ));
                 }
 
@@ -118,9 +168,37 @@ impl Parser {
             }
         };
         self.consume(RightParen, "Expected ')' after parameters")?;
-        self.consume(LeftBrace, &format!("Expected '{{' before {:?} body", kind))?;
-        let body = self.block()?;
-        return Ok(Function::new(name, params, body).into());
+        Ok(params)
+    }
+    /// Whether the tokens after a `(` are the parameters of an arrow
+    /// function, as in `(a, b) => a + b`.
+    fn is_arrow(&self) -> bool {
+        use TokenType::{Arrow, Comma, Identifier, RightParen};
+
+        let mut tokens = self.tokens[self.current..].iter().map(|t| t.ty).peekable();
+        if tokens.next_if_eq(&RightParen).is_some() {
+            return tokens.next() == Some(Arrow);
+        }
+        loop {
+            if tokens.next() != Some(Identifier) {
+                return false;
+            }
+            match tokens.next() {
+                Some(Comma) => continue,
+                Some(RightParen) => return tokens.next() == Some(Arrow),
+                _ => return false,
+            }
+        }
+    }
+    /// Build a lambda named after the token that introduced it.
+    fn lambda(&mut self, keyword: &Token, params: Vec<Token>, body: Vec<Stmt>) -> Expr {
+        let name = Token {
+            ty: TokenType::Identifier,
+            lexeme: "lambda".into(),
+            ..keyword.clone()
+        };
+        let function = Function::new(name, params, body, MethodKind::Method);
+        Lambda::new(Rc::new(function), None).into()
     }
     fn var_declaration(&mut self) -> ParseResult<Stmt> {
         use TokenType::{Equal, Identifier, Semicolon};
@@ -137,9 +215,12 @@ impl Parser {
     }
 
     fn statement(&mut self) -> ParseResult<Stmt> {
-        use TokenType::{For, If, LeftBrace, Print, Return, While};
+        use TokenType::{
+            Break, Continue, For, If, LeftBrace, Print, Return, Semicolon, Throw, Try, While,
+        };
 
-        if check!(self.peek(), For) {
+        let start = self.start();
+        let stmt = if check!(self.peek(), For) {
             self.advance();
             self.for_statement()
         } else if check!(self.peek(), If) {
@@ -154,12 +235,31 @@ impl Parser {
         } else if check!(self.peek(), While) {
             self.advance();
             self.while_statement()
+        } else if check!(self.peek(), Break) {
+            let keyword = self.advance().clone();
+            self.consume(Semicolon, "Expected ';' after 'break'.")?;
+            Ok(stmt::Break::new(keyword).into())
+        } else if check!(self.peek(), Continue) {
+            let keyword = self.advance().clone();
+            self.consume(Semicolon, "Expected ';' after 'continue'.")?;
+            Ok(stmt::Continue::new(keyword).into())
+        } else if check!(self.peek(), Throw) {
+            let keyword = self.advance().clone();
+            let value = self.expression()?;
+            self.consume(Semicolon, "Expected ';' after thrown value.")?;
+            Ok(stmt::Throw::new(keyword, value).into())
+        } else if check!(self.peek(), Try) {
+            self.advance();
+            self.try_statement()
+        } else if self.is_import() {
+            self.import_statement()
         } else if check!(self.peek(), LeftBrace) {
             self.advance();
             Ok(Block::new(self.block()?).into())
         } else {
             self.expression_statement()
-        }
+        }?;
+        Ok(stmt.with_span(self.span_from(start)))
     }
 
     fn block(&mut self) -> ParseResult<Vec<Stmt>> {
@@ -197,23 +297,79 @@ impl Parser {
             increment = Some(self.expression()?);
         }
         self.consume(RightParen, "Expected ')' after for clauses")?;
-        let mut body = self.statement()?;
+        let body = self.statement()?;
 
-        if let Some(increment) = increment {
-            body = Block::new(vec![body, Expression::new(increment).into()]).into();
-        }
         let condition = match condition {
             Some(v) => v,
             None => Literal::new(true.into(), None).into(),
         };
 
-        body = While::new(condition, Box::new(body)).into();
+        // the increment stays separate from the body so that `continue`
+        // still runs it
+        let mut body = While::new(condition, Box::new(body), increment).into();
 
         if let Some(initializer) = initializer {
             body = Block::new(vec![initializer, body]).into();
         }
         Ok(body)
     }
+    fn try_statement(&mut self) -> ParseResult<Stmt> {
+        use TokenType::{Catch, Finally, Identifier, LeftBrace, LeftParen, RightParen};
+
+        self.consume(LeftBrace, "Expected '{' after 'try'.")?;
+        let body = self.block()?;
+        let mut catch = None;
+        if check!(self.peek(), Catch) {
+            self.advance();
+            self.consume(LeftParen, "Expected '(' after 'catch'.")?;
+            let name = self
+                .consume(Identifier, "Expected error variable name.")?
+                .clone();
+            self.consume(RightParen, "Expected ')' after error variable.")?;
+            self.consume(LeftBrace, "Expected '{' before catch body.")?;
+            catch = Some((name, self.block()?));
+        }
+        let mut finally = None;
+        if check!(self.peek(), Finally) {
+            self.advance();
+            self.consume(LeftBrace, "Expected '{' after 'finally'.")?;
+            finally = Some(self.block()?);
+        }
+        if catch.is_none() && finally.is_none() {
+            let token = self.peek().unwrap().clone();
+            return self.error(&token, "Expected 'catch' or 'finally' after try block.");
+        }
+        Ok(stmt::Try::new(body, catch, finally).into())
+    }
+    fn import_statement(&mut self) -> ParseResult<Stmt> {
+        use TokenType::{Comma, Identifier, Semicolon, String};
+
+        let keyword = self.advance().clone();
+        let path = self
+            .consume(String, "Expected a module path string.")?
+            .clone();
+        let (alias, names) = if keyword.lexeme == "from" {
+            self.consume_word("import", "Expected 'import' after module path.")?;
+            let mut names = vec![self
+                .consume(Identifier, "Expected name to import.")?
+                .clone()];
+            while check!(self.peek(), Comma) {
+                self.advance();
+                names.push(
+                    self.consume(Identifier, "Expected name to import.")?
+                        .clone(),
+                );
+            }
+            (None, names)
+        } else {
+            self.consume_word("as", "Expected 'as' after module path.")?;
+            let alias = self.consume(Identifier, "Expected module name after 'as'.")?;
+            (Some(alias.clone()), Vec::new())
+        };
+        self.consume(Semicolon, "Expected ';' after import.")?;
+        Ok(stmt::Import::new(keyword, path, alias, names).into())
+    }
+
     fn if_statement(&mut self) -> ParseResult<Stmt> {
         use TokenType::{Else, LeftParen, RightParen};
 
@@ -248,7 +404,7 @@ impl Parser {
         let cond = self.expression()?;
         self.consume(RightParen, "Expected ')' after 'while condition'.")?;
         let body = self.statement()?;
-        Ok(While::new(cond, Box::new(body)).into())
+        Ok(While::new(cond, Box::new(body), None).into())
     }
 
     fn print_statement(&mut self) -> ParseResult<Stmt> {
@@ -274,29 +430,36 @@ impl Parser {
     fn and(&mut self) -> ParseResult<Expr> {
         use TokenType::And;
 
+        let start = self.start();
         let mut expr = self.equality()?;
 
         while check!(self.peek(), And) {
             let operator = self.advance().clone();
             let right = self.equality()?;
-            expr = Logical::new(Box::new(expr), operator, Box::new(right), None).into()
+            expr = Logical::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into()
         }
         Ok(expr)
     }
     fn or(&mut self) -> ParseResult<Expr> {
         use TokenType::Or;
 
+        let start = self.start();
         let mut expr = self.and()?;
 
         while check!(self.peek(), Or) {
             let operator = self.advance().clone();
             let right = self.and()?;
-            expr = Logical::new(Box::new(expr), operator, Box::new(right), None).into();
+            expr = Logical::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
         }
         Ok(expr)
     }
     fn assignment(&mut self) -> ParseResult<Expr> {
         use TokenType::Equal;
+        let start = self.start();
         let expr = self.or()?;
 
         if check!(self.peek(), Equal) {
@@ -305,19 +468,29 @@ impl Parser {
 
             let value = self.assignment()?;
             return match &expr {
-                Expr::Variable(var) => {
-                    Ok(Assign::new(var.name.clone(), Box::new(value), None).into())
-                }
+                Expr::Variable(var) => Ok(Assign::new(var.name.clone(), Box::new(value), None)
+                    .with_span(self.span_from(start))
+                    .into()),
                 Expr::Get(get) => Ok(Set::new(
                     Box::clone(&get.object),
                     get.name.clone(),
                     Box::new(value),
                     None,
                 )
+                .with_span(self.span_from(start))
+                .into()),
+                Expr::Index(index) => Ok(SetIndex::new(
+                    Box::clone(&index.object),
+                    index.bracket.clone(),
+                    Box::clone(&index.index),
+                    Box::new(value),
+                    None,
+                )
+                .with_span(self.span_from(start))
                 .into()),
-                _ => Err(ParseError::new(
+                _ => Err(ParseError::at(
                     "Invalid l value for assignment",
-                    (&equals).line,
+                    equals.span,
                 )),
             };
         }
@@ -325,11 +498,14 @@ impl Parser {
     }
     fn equality(&mut self) -> ParseResult<Expr> {
         use TokenType::{BangEqual, EqualEqual};
+        let start = self.start();
         let mut expr = self.comparision()?;
         while check!(self.peek(), EqualEqual | BangEqual) {
             let operator = (*self.advance()).clone();
             let right = self.comparision()?;
-            expr = Binary::new(Box::new(expr), operator, Box::new(right), None).into()
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into()
         }
         Ok(expr)
     }
@@ -337,62 +513,146 @@ impl Parser {
     fn comparision(&mut self) -> ParseResult<Expr> {
         use TokenType::{Greater, GreaterEqual, Less, LessEqual};
 
-        let mut expr = self.term()?;
+        let start = self.start();
+        let mut expr = self.bit_or()?;
 
         while check!(self.peek(), Greater | GreaterEqual | Less | LessEqual) {
+            let operator = (*self.advance()).clone();
+            let right = self.bit_or()?;
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
+        }
+        Ok(expr)
+    }
+    fn bit_or(&mut self) -> ParseResult<Expr> {
+        use TokenType::Pipe;
+        let start = self.start();
+        let mut expr = self.bit_xor()?;
+
+        while check!(self.peek(), Pipe) {
+            let operator = (*self.advance()).clone();
+            let right = self.bit_xor()?;
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
+        }
+        Ok(expr)
+    }
+
+    fn bit_xor(&mut self) -> ParseResult<Expr> {
+        use TokenType::Caret;
+        let start = self.start();
+        let mut expr = self.bit_and()?;
+
+        while check!(self.peek(), Caret) {
+            let operator = (*self.advance()).clone();
+            let right = self.bit_and()?;
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
+        }
+        Ok(expr)
+    }
+
+    fn bit_and(&mut self) -> ParseResult<Expr> {
+        use TokenType::Ampersand;
+        let start = self.start();
+        let mut expr = self.shift()?;
+
+        while check!(self.peek(), Ampersand) {
+            let operator = (*self.advance()).clone();
+            let right = self.shift()?;
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
+        }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> ParseResult<Expr> {
+        use TokenType::{GreaterGreater, LessLess};
+        let start = self.start();
+        let mut expr = self.term()?;
+
+        while check!(self.peek(), LessLess | GreaterGreater) {
             let operator = (*self.advance()).clone();
             let right = self.term()?;
-            expr = Binary::new(Box::new(expr), operator, Box::new(right), None).into();
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
         }
         Ok(expr)
     }
+
     fn term(&mut self) -> ParseResult<Expr> {
         use TokenType::{Minus, Plus};
+        let start = self.start();
         let mut expr = self.factor()?;
 
         while check!(self.peek(), Minus | Plus) {
             let operator = (*self.advance()).clone();
             let right = self.factor()?;
-            expr = Binary::new(Box::new(expr), operator, Box::new(right), None).into();
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
         }
         Ok(expr)
     }
 
     fn factor(&mut self) -> ParseResult<Expr> {
-        use TokenType::{Slash, Star};
+        use TokenType::{Percent, Slash, Star, TildeSlash};
+        let start = self.start();
         let mut expr = self.unary()?;
 
-        while check!(self.peek(), Slash | Star) {
+        while check!(self.peek(), Slash | Star | Percent | TildeSlash) {
             let operator = (*self.advance()).clone();
             let right = self.unary()?;
-            expr = Binary::new(Box::new(expr), operator, Box::new(right), None).into();
+            expr = Binary::new(Box::new(expr), operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into();
         }
         Ok(expr)
     }
 
     fn unary(&mut self) -> ParseResult<Expr> {
-        use TokenType::{Bang, Minus};
+        use TokenType::{Bang, Minus, Tilde};
 
-        if check!(self.peek(), Bang | Minus) {
+        if check!(self.peek(), Bang | Minus | Tilde) {
+            let start = self.start();
             let operator = (*self.advance()).clone();
             let right = self.unary()?;
-            Ok(Unary::new(operator, Box::new(right), None).into())
+            Ok(Unary::new(operator, Box::new(right), None)
+                .with_span(self.span_from(start))
+                .into())
         } else {
             self.call()
         }
     }
     fn call(&mut self) -> ParseResult<Expr> {
-        use TokenType::{Dot, Identifier, LeftParen};
+        use TokenType::{Dot, Identifier, LeftBracket, LeftParen, RightBracket};
 
+        let start = self.start();
         let mut expr = self.primary()?;
         loop {
             if check!(self.peek(), LeftParen) {
                 self.advance();
-                expr = self.handle_call(expr)?;
+                expr = self.handle_call(expr)?.with_span(self.span_from(start));
             } else if check!(self.peek(), Dot) {
                 self.advance();
-                let name: &Token = self.consume(Identifier, "Expected property name after '.'")?;
-                expr = Get::new(Box::new(expr), name.clone(), None).into()
+                let name = self
+                    .consume(Identifier, "Expected property name after '.'")?
+                    .clone();
+                expr = Get::new(Box::new(expr), name, None)
+                    .with_span(self.span_from(start))
+                    .into()
+            } else if check!(self.peek(), LeftBracket) {
+                let bracket = self.advance().clone();
+                let index = self.expression()?;
+                self.consume(RightBracket, "Expected ']' after index")?;
+                expr = Index::new(Box::new(expr), bracket, Box::new(index), None)
+                    .with_span(self.span_from(start))
+                    .into()
             } else {
                 break;
             }
@@ -407,9 +667,9 @@ impl Parser {
         if !check!(self.peek(), RightParen) {
             loop {
                 if args.len() >= 255 {
-                    return Err(ParseError::new(
+                    return Err(ParseError::at(
                         "Can't have more than 255 arguments",
-                        self.peek().unwrap().line,
+                        self.peek().unwrap().span,
                     ));
                 }
                 args.push(self.expression()?);
@@ -425,21 +685,82 @@ impl Parser {
     }
     fn primary(&mut self) -> ParseResult<Expr> {
         use TokenType::{
-            Dot, False, Identifier, LeftParen, Nil, Number, RightParen, Semicolon, String, This,
-            True,
+            Colon, Comma, Dot, False, Identifier, LeftBrace, LeftBracket, LeftParen, Nil, Number,
+            RightBrace, RightBracket, RightParen, Semicolon, String, This, True,
         };
-        Ok(match self.advance().ty {
+        let start = self.start();
+        let ty = self.advance().ty;
+        let expr: Expr = match ty {
             False => LoxType::False.into(),
             True => LoxType::True.into(),
             Nil => LoxType::Nil.into(),
+            // the rest of a string after `${...}` can't start an expression
+            String | TokenType::Interpolation if self.previous().lexeme.starts_with('}') => {
+                let token = self.previous().clone();
+                self.error::<Expr>(&token, "Expected expression")?
+            }
             Number | String => self.previous().literal.clone().into(),
+            TokenType::Interpolation => self.interpolation()?,
             Identifier => Variable::new(self.previous().clone(), None).into(),
             This => expr::This::new(self.previous().clone(), None).into(),
+            LeftParen if self.is_arrow() => {
+                let paren = self.previous().clone();
+                let params = self.parameters()?;
+                let arrow = self.advance().clone();
+                // a brace starts a block body, so a map to return needs
+                // parentheses, as in `(k) => ({k: 1})`
+                let body = if check!(self.peek(), LeftBrace) {
+                    self.advance();
+                    self.block()?
+                } else {
+                    let value = self.expression()?;
+                    let span = value.span();
+                    vec![Return::new(arrow, Some(value)).with_span(span).into()]
+                };
+                self.lambda(&paren, params, body)
+            }
+            TokenType::Fun => {
+                let keyword = self.previous().clone();
+                self.consume(LeftParen, "Expected '(' after 'fun'")?;
+                let params = self.parameters()?;
+                self.consume(LeftBrace, "Expected '{' before lambda body")?;
+                let body = self.block()?;
+                self.lambda(&keyword, params, body)
+            }
             LeftParen => {
                 let expr = self.expression()?;
                 self.consume(RightParen, "Expected ')' after expression")?;
                 Grouping::new(Box::new(expr), None).into()
             }
+            LeftBracket => {
+                let bracket = self.previous().clone();
+                let mut elements = Vec::new();
+                while !check!(self.peek(), RightBracket) && !self.is_at_end() {
+                    elements.push(self.expression()?);
+                    if !check!(self.peek(), Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.consume(RightBracket, "Expected ']' after list elements")?;
+                List::new(bracket, elements, None).into()
+            }
+            LeftBrace => {
+                let brace = self.previous().clone();
+                let mut entries = Vec::new();
+                while !check!(self.peek(), RightBrace) && !self.is_at_end() {
+                    let key = self.expression()?;
+                    self.consume(Colon, "Expected ':' after map key")?;
+                    let value = self.expression()?;
+                    entries.push((key, value));
+                    if !check!(self.peek(), Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.consume(RightBrace, "Expected '}' after map entries")?;
+                Map::new(brace, entries, None).into()
+            }
             TokenType::Super => {
                 let kw = self.previous().clone();
                 self.consume(Dot, "Expect a . after 'super'")?;
@@ -462,8 +783,46 @@ impl Parser {
                 let val = self.peek().unwrap();
                 self.error::<Expr>(&val.clone(), "Expected expression")?
             }
-        })
+        };
+        Ok(expr.with_span(self.span_from(start)))
+    }
+    /// Desugar `"a${x}b"` into `"a" + str(x) + "b"`, starting after the
+    /// first `Interpolation` token.
+    fn interpolation(&mut self) -> ParseResult<Expr> {
+        use TokenType::{Interpolation, String};
+
+        let concat = |left: Expr, right: Expr, span: Span| -> Expr {
+            let plus = Token::new(TokenType::Plus, "+".into(), LoxType::InternalNoValue, span);
+            Binary::new(Box::new(left), plus, Box::new(right), None)
+                .with_span(span)
+                .into()
+        };
+        let first = self.previous().clone();
+        let mut expr: Expr = Literal::new(first.literal, None)
+            .with_span(first.span)
+            .into();
+        loop {
+            let start = self.start();
+            let value = self.expression()?;
+            let span = self.span_from(start);
+            let paren = Token::new(TokenType::RightParen, ")".into(), LoxType::Nil, span);
+            let callee = Literal::new(stringify_fn(), None).with_span(span);
+            let value =
+                Call::new(Box::new(callee.into()), paren, vec![value], None).with_span(span);
+            expr = concat(expr, value.into(), first.span.to(span));
+
+            let segment = self.advance().clone();
+            if !matches!(segment.ty, Interpolation | String) {
+                return self.error(&segment, "Expected '}' after interpolated expression");
+            }
+            let literal = Literal::new(segment.literal, None).with_span(segment.span);
+            expr = concat(expr, literal.into(), first.span.to(segment.span));
+            if segment.ty == String {
+                return Ok(expr);
+            }
+        }
     }
+
     #[allow(dead_code)]
     fn synchronize(&mut self) {
         self.advance();
@@ -479,7 +838,9 @@ impl Parser {
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => break,
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => break,
                 _ => (),
             }
             self.advance();
@@ -488,8 +849,8 @@ impl Parser {
 
     fn error<T>(&mut self, t: &Token, err: &str) -> ParseResult<T> {
         Err(match t.ty {
-            TokenType::EOF => ParseError::new(format!("at the end: {err}"), t.line),
-            _ => ParseError::new(format!("at '{}': {}", t.lexeme, err), t.line),
+            TokenType::EOF => ParseError::at(format!("at the end: {err}"), t.span),
+            _ => ParseError::at(format!("at '{}': {}", t.lexeme, err), t.span),
         })
     }
 
@@ -503,6 +864,39 @@ impl Parser {
         }
     }
 
+    /// Whether the next token is the identifier `word`. `import`, `from` and
+    /// `as` are keywords only where an import expects them, so scripts can
+    /// still use them as names.
+    fn check_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(t) if t.ty == TokenType::Identifier && t.lexeme == word)
+    }
+
+    fn consume_word(&mut self, word: &str, err: &str) -> ParseResult<&Token> {
+        if self.check_word(word) {
+            Ok(self.advance())
+        } else {
+            let token = &self.peek().unwrap().clone();
+            self.error(token, err)
+        }
+    }
+
+    /// Whether an import starts here: `import` or `from` followed by a
+    /// module path.
+    fn is_import(&self) -> bool {
+        (self.check_word("import") || self.check_word("from"))
+            && check!(self.tokens.get(self.current + 1), TokenType::String)
+    }
+
+    /// Span of the next token, used to mark where a node begins.
+    fn start(&self) -> Span {
+        self.peek().map(|t| t.span).unwrap_or_default()
+    }
+
+    /// Span from `start` up to and including the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.current)
     }
@@ -1,23 +1,22 @@
-use std::{collections::HashMap, vec};
+use std::{collections::HashMap, rc::Rc, vec};
 
 use crate::{
-    errors::{ResolverError, ResolverResult},
-    interpreter::Interpreter,
+    errors::ResolverError,
     syntax::{
-        expr::{self, Expr},
+        expr::Expr,
         stmt::{self, Stmt},
     },
     tokens::token::{LoxType, Token},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum FunctionState {
     None,
     Function,
     Method,
     Initializer,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum ClassState {
     None,
     Class,
@@ -25,19 +24,22 @@ enum ClassState {
 }
 #[derive(Debug)]
 pub struct Resolver {
-    pub interpreter: Interpreter,
     scopes: Vec<HashMap<String, bool>>,
     function_state: FunctionState,
     class_state: ClassState,
+    /// Number of loops around the current statement within this function.
+    loop_depth: usize,
+    errors: Vec<ResolverError>,
 }
 
 impl Resolver {
-    pub fn new(interpreter: Interpreter) -> Self {
+    pub fn new() -> Self {
         Self {
-            interpreter,
             scopes: vec![Default::default()],
             function_state: FunctionState::None,
             class_state: ClassState::None,
+            loop_depth: 0,
+            errors: Vec::new(),
         }
     }
 
@@ -47,322 +49,259 @@ impl Resolver {
     fn end_scope(&mut self) {
         self.scopes.pop();
     }
-    pub fn resolve_statements(self, stmts: Vec<Stmt>) -> ResolverResult<Vec<Stmt>> {
-        let mut res = Vec::new();
-        let mut this = self;
+    /// Resolve every statement, collecting all errors instead of stopping at
+    /// the first one.
+    pub fn resolve_statements(&mut self, stmts: &mut [Stmt]) -> Result<(), Vec<ResolverError>> {
+        self.resolve_stmts(stmts);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+    fn resolve_stmts(&mut self, stmts: &mut [Stmt]) {
         for stmt in stmts {
-            let st;
-            (st, this) = this.resolve_stmt(stmt)?;
-            res.push(st);
+            self.resolve_stmt(stmt);
         }
-
-        Ok((res, this))
     }
-    fn resolve_stmt(self, stmt: Stmt) -> ResolverResult<Stmt> {
+    fn error<T: Into<String>>(&mut self, message: T, token: &Token) {
+        self.errors.push(ResolverError::at(message, token.span));
+    }
+    fn resolve_stmt(&mut self, stmt: &mut Stmt) {
         match stmt {
             Stmt::Block(e) => self.handle_block_stmt(e),
             Stmt::Expression(e) => self.handle_expr_stmt(e),
-            Stmt::Function(e) => self.resolve_function_stmt(e),
+            Stmt::Function(e) => self.resolve_function_stmt(Rc::make_mut(e)),
             Stmt::If(e) => self.handle_if_stmt(e),
             Stmt::Print(e) => self.handle_print_stmt(e),
             Stmt::Return(ret) => self.handle_return_stmt(ret),
             Stmt::Var(v) => self.handle_var_stmt(v),
             Stmt::While(wh) => self.handle_while_stmt(wh),
             Stmt::Class(cls) => self.handle_class_stmt(cls),
+            Stmt::Throw(e) => self.resolve_expr(&mut e.value),
+            Stmt::Try(e) => self.handle_try_stmt(e),
+            Stmt::Import(e) => {
+                for name in e.alias.iter().chain(&e.names) {
+                    self.declare(name);
+                    self.define(name);
+                }
+            }
+            Stmt::Break(e) => self.handle_loop_jump(&e.keyword),
+            Stmt::Continue(e) => self.handle_loop_jump(&e.keyword),
         }
     }
-    fn handle_class_stmt(mut self, mut cls: stmt::Class) -> ResolverResult<Stmt> {
+    fn handle_class_stmt(&mut self, cls: &mut stmt::Class) {
         let curr = self.class_state;
         self.class_state = ClassState::Class;
 
-        match self.declare(&cls.name) {
-            Err(message) => {
-                return Err(ResolverError::new(message, cls.name.line, self.interpreter));
-            }
-            _ => (),
-        };
+        self.declare(&cls.name);
         self.define(&cls.name);
-        let mut this = self;
-        if let Some(sc) = cls.superclass {
+        let mut has_super_scope = false;
+        if let Some(sc) = &mut cls.superclass {
             if sc.name.lexeme == cls.name.lexeme {
-                return Err(ResolverError::new(
-                    "A class cannot inherit itself",
-                    cls.name.line,
-                    this.interpreter,
-                ));
-            }
-            this.class_state = ClassState::Subclass;
-            let s;
-            (s, this) = this.resolve_expr(sc.into())?;
-            if let Expr::Variable(s) = s {
-                cls.superclass = Some(s)
+                self.error("A class cannot inherit itself", &cls.name);
             } else {
-                panic!("Resolver error superclass back after resolution!")
+                self.class_state = ClassState::Subclass;
+                sc.depth = self.resolve_local(&sc.name);
+                self.begin_scope();
+                self.scopes
+                    .last_mut()
+                    .map(|scope| scope.insert("super".into(), true));
+                has_super_scope = true;
             }
-            this.begin_scope();
-            this.scopes
-                .last_mut()
-                .map(|scope| scope.insert("super".into(), true));
         }
 
-        this.begin_scope();
-        this.scopes
+        self.begin_scope();
+        self.scopes
             .last_mut()
             .map(|scope| scope.insert("this".into(), true));
 
-        let mut methods = Vec::with_capacity(cls.methods.len());
-        for method in cls.methods {
-            let meth;
+        for method in &mut cls.methods {
             let decl = if method.name.lexeme == "init" {
                 FunctionState::Initializer
             } else {
                 FunctionState::Method
             };
-            (meth, this) = this.resolve_function(method, decl)?;
-            match meth {
-                Stmt::Function(fun) => {
-                    methods.push(fun);
-                }
-                _ => panic!("Expected function??"),
-            }
+            self.resolve_function(Rc::make_mut(method), decl);
         }
-        if !matches!(cls.superclass, None) {
-            this.end_scope();
+        // `this` is the class itself in a class method
+        for method in &mut cls.class_methods {
+            self.resolve_function(Rc::make_mut(method), FunctionState::Method);
         }
-        this.end_scope();
-        cls.methods = methods;
-        this.class_state = curr;
-        Ok((cls.into(), this))
+        if has_super_scope {
+            self.end_scope();
+        }
+        self.end_scope();
+        self.class_state = curr;
     }
-    fn handle_while_stmt(self, mut e: stmt::While) -> ResolverResult<Stmt> {
-        let mut this = self;
-        let cond;
-        let body;
-        (cond, this) = this.resolve_expr(e.cond)?;
-        (body, this) = this.resolve_stmt(*e.body)?;
-        e.cond = cond;
-        e.body = Box::new(body);
-        Ok((e.into(), this))
+    fn handle_while_stmt(&mut self, e: &mut stmt::While) {
+        self.resolve_expr(&mut e.cond);
+        self.loop_depth += 1;
+        self.resolve_stmt(&mut e.body);
+        if let Some(increment) = &mut e.increment {
+            self.resolve_expr(increment);
+        }
+        self.loop_depth -= 1;
     }
 
-    fn handle_var_stmt(mut self, mut e: stmt::Var) -> ResolverResult<Stmt> {
-        match self.declare(&e.name) {
-            Err(message) => {
-                return Err(ResolverError::new(message, e.name.line, self.interpreter));
-            }
-            _ => (),
+    fn handle_try_stmt(&mut self, e: &mut stmt::Try) {
+        self.begin_scope();
+        self.resolve_stmts(&mut e.body);
+        self.end_scope();
+        if let Some((name, handler)) = &mut e.catch {
+            self.begin_scope();
+            self.declare(name);
+            self.define(name);
+            self.resolve_stmts(handler);
+            self.end_scope();
         }
-        let (init, mut this) = match &e.initializer {
-            Expr::Literal(lit) => {
-                if !matches!(lit.value, LoxType::Nil) {
-                    self.resolve_expr(e.initializer)?
-                } else {
-                    (e.initializer, self)
-                }
-            }
-            _ => self.resolve_expr(e.initializer)?,
+        if let Some(finally) = &mut e.finally {
+            self.begin_scope();
+            self.resolve_stmts(finally);
+            self.end_scope();
+        }
+    }
+
+    fn handle_loop_jump(&mut self, keyword: &Token) {
+        if self.loop_depth == 0 {
+            let msg = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+            self.error(msg, keyword);
+        }
+    }
+
+    fn handle_var_stmt(&mut self, e: &mut stmt::Var) {
+        self.declare(&e.name);
+        match &e.initializer {
+            Expr::Literal(lit) if matches!(lit.value, LoxType::Nil) => (),
+            _ => self.resolve_expr(&mut e.initializer),
         };
-        this.define(&e.name);
-        e.initializer = init;
-        Ok((e.into(), this))
+        self.define(&e.name);
     }
 
-    fn handle_return_stmt(self, mut e: stmt::Return) -> ResolverResult<Stmt> {
+    fn handle_return_stmt(&mut self, e: &mut stmt::Return) {
         if matches!(self.function_state, FunctionState::None) {
-            return Err(ResolverError::new(
-                "Can't return from top level code",
-                e.keyword.line,
-                self.interpreter,
-            ));
+            return self.error("Can't return from top level code", &e.keyword);
         }
-        if let Some(val) = e.value {
+        if let Some(val) = &mut e.value {
             if matches!(self.function_state, FunctionState::Initializer) {
-                return Err(ResolverError::new(
-                    "Cannot return a value from an initializer",
-                    e.keyword.line,
-                    self.interpreter,
-                ));
+                return self.error("Cannot return a value from an initializer", &e.keyword);
             }
-            let (expr, this) = self.resolve_expr(val)?;
-            e.value = Some(expr);
-            Ok((e.into(), this))
-        } else {
-            Ok((e.into(), self))
+            self.resolve_expr(val);
         }
     }
-    fn handle_print_stmt(self, mut e: stmt::Print) -> ResolverResult<Stmt> {
-        let (expr, this) = self.resolve_expr(e.expression)?;
-        e.expression = expr;
-        Ok((e.into(), this))
+    fn handle_print_stmt(&mut self, e: &mut stmt::Print) {
+        self.resolve_expr(&mut e.expression)
     }
-    fn handle_if_stmt(self, mut e: stmt::If) -> ResolverResult<Stmt> {
-        let mut this = self;
-        let cond;
-        let then;
-        let else_;
-        (cond, this) = this.resolve_expr(e.cond)?;
-        (then, this) = this.resolve_stmt(*e.then_branch)?;
-        e.cond = cond;
-        e.then_branch = Box::new(then);
-        if let Some(else_branch) = e.else_branch {
-            (else_, this) = this.resolve_stmt(*else_branch)?;
-            e.else_branch = Some(Box::new(else_));
+    fn handle_if_stmt(&mut self, e: &mut stmt::If) {
+        self.resolve_expr(&mut e.cond);
+        self.resolve_stmt(&mut e.then_branch);
+        if let Some(else_branch) = &mut e.else_branch {
+            self.resolve_stmt(else_branch);
         }
-        Ok((e.into(), this))
     }
 
-    fn resolve_function_stmt(mut self, e: stmt::Function) -> ResolverResult<Stmt> {
-        match self.declare(&e.name) {
-            Err(message) => {
-                return Err(ResolverError::new(message, e.name.line, self.interpreter));
-            }
-            _ => (),
-        }
+    fn resolve_function_stmt(&mut self, e: &mut stmt::Function) {
+        self.declare(&e.name);
         self.define(&e.name);
-        let (e, this) = self.resolve_function(e, FunctionState::Function)?;
-        Ok((e.into(), this))
+        self.resolve_function(e, FunctionState::Function)
     }
 
-    fn handle_expr_stmt(self, mut e: stmt::Expression) -> ResolverResult<Stmt> {
-        let (expr, this) = self.resolve_expr(e.expression)?;
-        e.expression = expr;
-        Ok((e.into(), this))
+    fn handle_expr_stmt(&mut self, e: &mut stmt::Expression) {
+        self.resolve_expr(&mut e.expression)
     }
 
-    fn handle_block_stmt(self, mut e: stmt::Block) -> ResolverResult<Stmt> {
-        let mut this = self;
-        this.begin_scope();
-        let stmts;
-        (stmts, this) = this.resolve_statements(e.statements)?;
-        this.end_scope();
-        e.statements = stmts;
-        Ok((e.into(), this))
+    fn handle_block_stmt(&mut self, e: &mut stmt::Block) {
+        self.begin_scope();
+        self.resolve_stmts(&mut e.statements);
+        self.end_scope();
     }
 
-    fn resolve_expr(self, e: Expr) -> ResolverResult<Expr> {
+    fn resolve_expr(&mut self, e: &mut Expr) {
         match e {
-            Expr::Assign(mut e) => {
-                let mut this = self;
-                (*e.value, this) = this.resolve_expr(*e.value)?;
-                let t = &e.name.clone();
-                let e = this.resolve_local(e.into(), t);
-                Ok((e, this))
+            Expr::Assign(e) => {
+                self.resolve_expr(&mut e.value);
+                e.depth = self.resolve_local(&e.name);
             }
-            Expr::Binary(mut e) => {
-                let mut this = self;
-                (*e.left, this) = this.resolve_expr(*e.left)?;
-                (*e.right, this) = this.resolve_expr(*e.right)?;
-                Ok((e.into(), this))
+            Expr::Binary(e) => {
+                self.resolve_expr(&mut e.left);
+                self.resolve_expr(&mut e.right);
             }
-            Expr::Call(mut e) => {
-                let mut this;
-                (*e.callee, this) = self.resolve_expr(*e.callee)?;
-
-                let mut args = Vec::new();
-                for arg in e.args {
-                    let arg_;
-                    (arg_, this) = this.resolve_expr(arg)?;
-                    args.push(arg_);
+            Expr::Call(e) => {
+                self.resolve_expr(&mut e.callee);
+                for arg in &mut e.args {
+                    self.resolve_expr(arg);
                 }
-                e.args = args;
-                Ok((e.into(), this))
             }
-            Expr::Grouping(e) => {
-                let (res, this) = self.resolve_expr(*e.expression)?;
-                if let Expr::Grouping(res) = res {
-                    return Ok((res.into(), this));
-                } else {
-                    panic!("??")
+            Expr::Grouping(e) => self.resolve_expr(&mut e.expression),
+            Expr::Literal(_) => (),
+            Expr::Logical(e) => {
+                self.resolve_expr(&mut e.left);
+                self.resolve_expr(&mut e.right);
+            }
+            Expr::Unary(e) => self.resolve_expr(&mut e.right),
+            Expr::Variable(e) => {
+                if let Some(el) = self.scopes.last() {
+                    if let Some(false) = el.get(&e.name.lexeme) {
+                        return self
+                            .error("Can't read local variable in its own initializer", &e.name);
+                    }
                 }
+                e.depth = self.resolve_local(&e.name);
             }
-            Expr::Literal(e) => Ok((e.into(), self)),
-            Expr::Logical(mut e) => {
-                let mut this = self;
-                (*e.left, this) = this.resolve_expr(*e.left)?;
-
-                (*e.right, this) = this.resolve_expr(*e.right)?;
-                return Ok((e.into(), this));
+            Expr::Get(e) => self.resolve_expr(&mut e.object),
+            Expr::Set(e) => {
+                self.resolve_expr(&mut e.value);
+                self.resolve_expr(&mut e.object);
             }
-            Expr::Unary(mut e) => {
-                let this;
-                (*e.right, this) = self.resolve_expr(*e.right)?;
-                Ok((e.into(), this))
+            Expr::List(e) => {
+                for element in &mut e.elements {
+                    self.resolve_expr(element);
+                }
             }
-            Expr::Variable(e) => {
-                let mut this = self;
-                if let Some(el) = this.scopes.last_mut() {
-                    if let Some(val) = el.get(&e.name.lexeme) {
-                        if !val {
-                            return Err(ResolverError::new(
-                                "Can't read local variable in its own initializer",
-                                e.name.line,
-                                this.interpreter,
-                            ));
-                        }
-                    }
+            Expr::Map(e) => {
+                for (key, value) in &mut e.entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
                 }
-
-                let t = &e.name.clone();
-                let e = this.resolve_local(e.into(), t);
-                Ok((e, this))
             }
-            Expr::Get(mut e) => {
-                let (obj, this) = self.resolve_expr(*e.object)?;
-                e.object = Box::new(obj);
-                Ok((e.into(), this))
+            Expr::Index(e) => {
+                self.resolve_expr(&mut e.object);
+                self.resolve_expr(&mut e.index);
             }
-            Expr::Set(mut e) => {
-                let (value, this) = self.resolve_expr(*e.value)?;
-                let (obj, this) = this.resolve_expr(*e.object)?;
-                e.value = Box::new(value);
-                e.object = Box::new(obj);
-                Ok((e.into(), this))
+            Expr::SetIndex(e) => {
+                self.resolve_expr(&mut e.value);
+                self.resolve_expr(&mut e.object);
+                self.resolve_expr(&mut e.index);
+            }
+            Expr::Lambda(e) => {
+                self.resolve_function(Rc::make_mut(&mut e.function), FunctionState::Function)
             }
             Expr::This(e) => {
-                if !matches!(self.class_state, ClassState::Class) {
-                    return Err(ResolverError::new(
-                        "Cannot use 'this' outside of a class.",
-                        e.keyword.line,
-                        self.interpreter,
-                    ));
+                if matches!(self.class_state, ClassState::None) {
+                    return self.error("Cannot use 'this' outside of a class.", &e.keyword);
                 }
-                let t = &e.keyword.clone();
-                let e = self.resolve_local(e.into(), t);
-                Ok((e, self))
+                e.depth = self.resolve_local(&e.keyword);
             }
             Expr::Super(e) => {
                 if matches!(self.class_state, ClassState::None) {
-                    return Err(ResolverError::new(
-                        "Can't use 'super' outside of a class.",
-                        e.keyword.line,
-                        self.interpreter,
-                    ));
+                    return self.error("Can't use 'super' outside of a class.", &e.keyword);
                 }
                 if matches!(self.class_state, ClassState::Class) {
-                    return Err(ResolverError::new(
-                        "Can't use 'super' without a superclass.",
-                        e.keyword.line,
-                        self.interpreter,
-                    ));
+                    return self.error("Can't use 'super' without a superclass.", &e.keyword);
                 }
-                let t = &e.keyword.clone();
-                let e = self.resolve_local(e.into(), t);
-                Ok((e, self))
+                e.depth = self.resolve_local(&e.keyword);
             }
-        }
+        };
     }
 
-    fn declare(&mut self, name: &Token) -> Result<(), String> {
-        match self.scopes.last_mut() {
-            Some(top) => {
-                if top.contains_key(&name.lexeme) {
-                    return Err("Already a variable with this name in this scope.".into());
-                }
-                top.insert(name.lexeme.clone(), false);
-            }
-            None => (),
+    fn declare(&mut self, name: &Token) {
+        let Some(top) = self.scopes.last_mut() else {
+            return;
         };
-        Ok(())
+        if top.contains_key(&name.lexeme) {
+            return self.error("Already a variable with this name in this scope.", name);
+        }
+        top.insert(name.lexeme.clone(), false);
     }
 
     fn define(&mut self, name: &Token) {
@@ -370,41 +309,28 @@ impl Resolver {
             .last_mut()
             .map(|top| top.insert(name.lexeme.clone(), true));
     }
-    fn resolve_local(&self, mut e: Expr, name: &Token) -> Expr {
-        if let Some((_, i)) = self
-            .scopes
+    fn resolve_local(&self, name: &Token) -> Option<i32> {
+        self.scopes
             .iter()
             .rev()
             .zip(0..)
             .find(|(scope, _)| scope.contains_key(&name.lexeme))
-        {
-            e.set_depth(i);
-        };
-        e
-        // .map(|(_, i)| self.interpreter.resolve(e, i));
+            .map(|(_, i)| i)
     }
 
-    fn resolve_function(
-        mut self,
-        mut fun: stmt::Function,
-        state: FunctionState,
-    ) -> ResolverResult<Stmt> {
+    fn resolve_function(&mut self, fun: &mut stmt::Function, state: FunctionState) {
         let curr = self.function_state;
+        let loop_depth = self.loop_depth;
         self.function_state = state;
+        self.loop_depth = 0;
         self.begin_scope();
-        for param in (&fun.params).into_iter() {
-            match self.declare(param) {
-                Err(message) => {
-                    return Err(ResolverError::new(message, param.line, self.interpreter));
-                }
-                _ => (),
-            }
+        for param in fun.params.iter() {
+            self.declare(param);
             self.define(param);
         }
-        let (stmts, mut this) = self.resolve_statements(fun.body)?;
-        this.end_scope();
-        this.function_state = curr;
-        fun.body = stmts;
-        Ok((fun.into(), this))
+        self.resolve_stmts(&mut fun.body);
+        self.end_scope();
+        self.function_state = curr;
+        self.loop_depth = loop_depth;
     }
 }
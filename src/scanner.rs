@@ -1,5 +1,5 @@
 use crate::errors::{ScanError, ScanResult};
-use crate::tokens::token::{LoxType, Token};
+use crate::tokens::token::{LoxType, Span, Token};
 use crate::tokens::token_type::TokenType::{self};
 
 pub struct Scanner {
@@ -8,6 +8,13 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
+    /// Open `${` interpolations, each with the number of `{` nested inside
+    /// it that still have to be closed.
+    interpolations: Vec<usize>,
+    source_id: u32,
 }
 
 impl Scanner {
@@ -18,8 +25,18 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            interpolations: Vec::new(),
+            source_id: 0,
         }
     }
+    /// Mark every token as coming from the imported module `id`.
+    pub fn with_source_id(mut self, id: u32) -> Self {
+        self.source_id = id;
+        self
+    }
     #[inline]
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
@@ -27,16 +44,49 @@ impl Scanner {
     pub fn scan_tokens(mut self) -> ScanResult<Vec<Token>> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column(self.start);
             self.scan_token()?;
         }
+        if !self.interpolations.is_empty() {
+            return Err(self.error("Unterminated string interpolation"));
+        }
+        let end = Span::new(
+            self.current,
+            self.current,
+            self.line,
+            self.column(self.current),
+        )
+        .with_source(self.source_id);
         self.tokens.push(Token::new(
             TokenType::EOF,
             "".to_owned(),
             LoxType::InternalNoValue,
-            self.line,
+            end,
         ));
         Ok(self.tokens)
     }
+    /// 1-based column of the byte offset `at` on the current line.
+    fn column(&self, at: usize) -> usize {
+        self.source[self.line_start..at].chars().count() + 1
+    }
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
+            .with_source(self.source_id)
+    }
+    fn error<T: Into<String>>(&self, message: T) -> ScanError {
+        ScanError::at(message, self.span())
+    }
+    /// An error for the bytes `start..self.current` on the current line.
+    fn error_from<T: Into<String>>(&self, message: T, start: usize) -> ScanError {
+        let span = Span::new(start, self.current, self.line, self.column(start))
+            .with_source(self.source_id);
+        ScanError::at(message, span)
+    }
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
     #[inline]
     fn curr_char(&self) -> char {
         self.source.as_bytes()[self.current] as char
@@ -45,13 +95,14 @@ impl Scanner {
     fn advance(&mut self) -> char {
         let res = self.curr_char();
         self.current += 1;
-        return res as char;
+        return res;
     }
     #[inline]
     fn add_token(&mut self, t: TokenType, literal: LoxType) {
         let text = &self.source[self.start..self.current];
+        let span = self.span();
         self.tokens
-            .push(Token::new(t, text.to_owned(), literal, self.line));
+            .push(Token::new(t, text.to_owned(), literal, span));
     }
     fn scan_token(&mut self) -> ScanResult<()> {
         use TokenType::*;
@@ -59,14 +110,44 @@ impl Scanner {
         match c {
             '(' => self.add_token(LeftParen, LoxType::InternalNoValue),
             ')' => self.add_token(RightParen, LoxType::InternalNoValue),
-            '{' => self.add_token(LeftBrace, LoxType::InternalNoValue),
-            '}' => self.add_token(RightBrace, LoxType::InternalNoValue),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(LeftBrace, LoxType::InternalNoValue)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+                    self.handle_string()?
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.add_token(RightBrace, LoxType::InternalNoValue)
+                }
+                None => self.add_token(RightBrace, LoxType::InternalNoValue),
+            },
+            '[' => self.add_token(LeftBracket, LoxType::InternalNoValue),
+            ']' => self.add_token(RightBracket, LoxType::InternalNoValue),
             ',' => self.add_token(Comma, LoxType::InternalNoValue),
+            ':' => self.add_token(Colon, LoxType::InternalNoValue),
             '.' => self.add_token(Dot, LoxType::InternalNoValue),
             '-' => self.add_token(Minus, LoxType::InternalNoValue),
             '+' => self.add_token(Plus, LoxType::InternalNoValue),
             ';' => self.add_token(Semicolon, LoxType::InternalNoValue),
             '*' => self.add_token(Star, LoxType::InternalNoValue),
+            '%' => self.add_token(Percent, LoxType::InternalNoValue),
+            '&' => self.add_token(Ampersand, LoxType::InternalNoValue),
+            '|' => self.add_token(Pipe, LoxType::InternalNoValue),
+            '^' => self.add_token(Caret, LoxType::InternalNoValue),
+            '~' => {
+                let a = if self.consume_if('/') {
+                    TildeSlash
+                } else {
+                    Tilde
+                };
+                self.add_token(a, LoxType::InternalNoValue)
+            }
             '!' => {
                 let a = if self.consume_if('=') {
                     BangEqual
@@ -78,6 +159,8 @@ impl Scanner {
             '=' => {
                 let a = if self.consume_if('=') {
                     EqualEqual
+                } else if self.consume_if('>') {
+                    Arrow
                 } else {
                     Equal
                 };
@@ -86,6 +169,8 @@ impl Scanner {
             '<' => {
                 let a = if self.consume_if('=') {
                     LessEqual
+                } else if self.consume_if('<') {
+                    LessLess
                 } else {
                     Less
                 };
@@ -94,6 +179,8 @@ impl Scanner {
             '>' => {
                 let a = if self.consume_if('=') {
                     GreaterEqual
+                } else if self.consume_if('>') {
+                    GreaterGreater
                 } else {
                     Greater
                 };
@@ -111,46 +198,72 @@ impl Scanner {
                     self.add_token(Slash, LoxType::InternalNoValue);
                 }
             }
-            '0'..='9' => self.handle_number(),
+            '0'..='9' => self.handle_number()?,
             ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
+            '\n' => self.newline(),
             '"' => self.handle_string()?,
             'a'..='z' | 'A'..='Z' | '_' => self.handle_identifier(),
-            _ => Err(ScanError::new(format!("Unexpected token: {c}"), self.line))?,
+            _ => Err(self.error(format!("Unexpected token: {c}")))?,
         };
         Ok(())
     }
-    fn handle_number(&mut self) {
-        use TokenType::*;
-        while matches!(self.peek(), Some('0'..='9')) {
-            self.advance();
-        }
-        if matches!(self.peek(), Some('.')) && matches!(self.peek_next(), Some('0'..='9')) {
+    /// Consume digits of the given radix and `_` separators.
+    fn digits(&mut self, radix: u32) {
+        while matches!(self.peek(), Some(c) if c.is_digit(radix) || c == '_') {
             self.advance();
         }
-        while matches!(self.peek(), Some('0'..='9')) {
+    }
+
+    fn handle_number(&mut self) -> Result<(), ScanError> {
+        let first = &self.source[self.start..self.current];
+        let radix = match self.peek() {
+            Some('x' | 'X') if first == "0" => 16,
+            Some('b' | 'B') if first == "0" => 2,
+            _ => 10,
+        };
+        let mut is_float = false;
+        if radix == 10 {
+            self.digits(10);
+            if matches!(self.peek(), Some('.')) && matches!(self.peek_next(), Some('0'..='9')) {
+                self.advance();
+                self.digits(10);
+                is_float = true;
+            }
+        } else {
             self.advance();
+            self.digits(radix);
         }
 
-        self.add_token(
-            Number,
-            LoxType::Float(
-                self.source[self.start..self.current]
-                    .parse::<f64>()
-                    .unwrap(),
-            ),
-        )
+        let text = &self.source[self.start..self.current];
+        if text.ends_with('_') || text.contains("__") || text.contains("_.") {
+            return Err(self.error("Misplaced '_' in number literal."));
+        }
+        let digits = text.replace('_', "");
+        let value = if is_float {
+            LoxType::Float(digits.parse().expect("valid float literal"))
+        } else {
+            let digits = if radix == 10 {
+                &digits[..]
+            } else {
+                &digits[2..]
+            };
+            if digits.is_empty() {
+                return Err(self.error(format!("Expected digits after '{text}'.")));
+            }
+            match i64::from_str_radix(digits, radix) {
+                Ok(i) => LoxType::Int(i),
+                Err(_) => return Err(self.error("Integer literal is too large.")),
+            }
+        };
+        self.add_token(TokenType::Number, value);
+        Ok(())
     }
 
     fn handle_identifier(&mut self) {
         use TokenType::*;
-        loop {
-            if let Some(x) = self.peek() {
-                if x.is_alphanumeric() {
-                    self.advance();
-                } else {
-                    break;
-                }
+        while let Some(x) = self.peek() {
+            if x.is_alphanumeric() || x == '_' {
+                self.advance();
             } else {
                 break;
             }
@@ -158,9 +271,13 @@ impl Scanner {
         let text = &self.source[self.start..self.current];
         let tt = match text {
             "and" => And,
+            "break" => Break,
+            "catch" => Catch,
             "class" => Class,
+            "continue" => Continue,
             "else" => Else,
             "false" => False,
+            "finally" => Finally,
             "for" => For,
             "fun" => Fun,
             "if" => If,
@@ -170,32 +287,102 @@ impl Scanner {
             "return" => Return,
             "super" => Super,
             "this" => This,
+            "throw" => Throw,
             "true" => True,
+            "try" => Try,
             "var" => Var,
             "while" => While,
             _ => Identifier,
         };
         self.add_token(tt, LoxType::InternalNoValue);
     }
+    /// Scan the rest of a string literal after its opening `"`, or after
+    /// the `}` closing an interpolated expression.
+    ///
+    /// A segment that ends in `${` becomes an `Interpolation` token and the
+    /// scanner goes back to regular tokens until the matching `}`.
     fn handle_string(&mut self) -> ScanResult<()> {
         use TokenType::*;
-        while self.peek().is_some() && self.peek().unwrap() != '"' {
-            if self.peek().unwrap() == '\n' {
-                self.line += 1;
+        let mut value = std::string::String::new();
+        let mut chunk = self.current;
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated string")),
+                Some('"') => {
+                    value.push_str(&self.source[chunk..self.current]);
+                    self.advance();
+                    self.add_token(String, LoxType::String(value));
+                    return Ok(());
+                }
+                Some('$') if self.peek_next() == Some('{') => {
+                    value.push_str(&self.source[chunk..self.current]);
+                    self.current += 2;
+                    self.interpolations.push(0);
+                    self.add_token(Interpolation, LoxType::String(value));
+                    return Ok(());
+                }
+                Some('\\') => {
+                    value.push_str(&self.source[chunk..self.current]);
+                    value.push(self.escape()?);
+                    chunk = self.current;
+                }
+                Some('\n') => {
+                    self.advance();
+                    self.newline();
+                }
+                Some(_) => {
+                    self.advance();
+                }
             }
-            self.advance();
         }
+    }
 
-        if self.is_at_end() {
-            return Err(ScanError::new("Unterminated string", self.line));
-        }
+    /// Scan an escape sequence starting at the current `\\`.
+    fn escape(&mut self) -> ScanResult<char> {
+        let start = self.current;
         self.advance();
-
-        let value = self.source[(self.start + 1)..(self.current - 1)].to_owned();
-
-        self.add_token(String, LoxType::String(value));
-        Ok(())
-        // todo!("add support for escape sequences");
+        let Some(c) = self.peek() else {
+            return Err(self.error_from("Unterminated escape sequence", start));
+        };
+        self.advance();
+        Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            'u' => {
+                if !self.consume_if('{') {
+                    return Err(self.error_from("Expected '{' after \\u", start));
+                }
+                let digits = self.current;
+                while matches!(self.peek(), Some(c) if c.is_ascii_hexdigit()) {
+                    self.advance();
+                }
+                let hex = self.source[digits..self.current].to_owned();
+                if !self.consume_if('}') || hex.is_empty() || hex.len() > 6 {
+                    return Err(self.error_from("Unicode escapes must look like \\u{1F600}", start));
+                }
+                match char::from_u32(u32::from_str_radix(&hex, 16).expect("hex digits")) {
+                    Some(c) => c,
+                    None => {
+                        return Err(
+                            self.error_from(format!("Invalid unicode code point {hex}"), start)
+                        )
+                    }
+                }
+            }
+            c if c.is_ascii() && !c.is_ascii_control() => {
+                return Err(self.error_from(format!("Unknown escape sequence \\{c}"), start))
+            }
+            _ => {
+                // not a printable ascii character, do not split it
+                self.current -= 1;
+                return Err(self.error_from("Unknown escape sequence", start));
+            }
+        })
     }
 
     #[inline]
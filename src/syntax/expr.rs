@@ -1,8 +1,12 @@
+use std::rc::Rc;
+
 use crate::{
     gen_struct,
     tokens::token::{LoxType, Token},
 };
 
+use super::stmt::Function;
+
 gen_struct!(Expr,
     Binary, left: Box<Expr>, operator: Token, right: Box<Expr>, depth:Option<i32>;
     Call, callee: Box<Expr>, paren: Token,args: Vec<Expr>,depth:Option<i32>;
@@ -15,16 +19,18 @@ gen_struct!(Expr,
     Logical, left: Box<Expr>, operator: Token, right: Box<Expr>,depth:Option<i32>;
     Unary, operator: Token, right: Box<Expr>,depth:Option<i32>;
     Variable, name: Token,depth:Option<i32>;
-    Assign, name: Token, value: Box<Expr>,depth:Option<i32>
+    Assign, name: Token, value: Box<Expr>,depth:Option<i32>;
+    List, bracket: Token, elements: Vec<Expr>, depth: Option<i32>;
+    Map, brace: Token, entries: Vec<(Expr, Expr)>, depth: Option<i32>;
+    Index, object: Box<Expr>, bracket: Token, index: Box<Expr>, depth: Option<i32>;
+    SetIndex, object: Box<Expr>, bracket: Token, index: Box<Expr>, value: Box<Expr>, depth: Option<i32>;
+    Lambda, function: Rc<Function>, depth: Option<i32>
 );
 
 impl Default for Expr {
     #[inline]
     fn default() -> Self {
-        Expr::Literal(Literal {
-            value: LoxType::InternalNoValue,
-            depth: None,
-        })
+        Expr::Literal(Literal::new(LoxType::InternalNoValue, None))
     }
 }
 impl From<LoxType> for Literal {
@@ -44,7 +50,7 @@ impl From<LoxType> for Expr {
 
 impl Expr {
     pub fn set_depth(&mut self, dpth: i32) {
-        let dpth  = Some(dpth);
+        let dpth = Some(dpth);
         match self {
             Expr::Assign(x) => x.depth = dpth,
             Expr::Binary(x) => x.depth = dpth,
@@ -58,6 +64,11 @@ impl Expr {
             Expr::Set(x) => x.depth = dpth,
             Expr::This(x) => x.depth = dpth,
             Expr::Super(x) => x.depth = dpth,
+            Expr::List(x) => x.depth = dpth,
+            Expr::Map(x) => x.depth = dpth,
+            Expr::Index(x) => x.depth = dpth,
+            Expr::SetIndex(x) => x.depth = dpth,
+            Expr::Lambda(x) => x.depth = dpth,
         };
     }
     pub fn get_depth(&self) -> Option<i32> {
@@ -74,6 +85,11 @@ impl Expr {
             Expr::Set(x) => x.depth,
             Expr::This(x) => x.depth,
             Expr::Super(x) => x.depth,
+            Expr::List(x) => x.depth,
+            Expr::Map(x) => x.depth,
+            Expr::Index(x) => x.depth,
+            Expr::SetIndex(x) => x.depth,
+            Expr::Lambda(x) => x.depth,
         }
     }
 }
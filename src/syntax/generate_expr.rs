@@ -1,6 +1,14 @@
 #[macro_export]
 macro_rules! gen_struct {
-    ($st_name:ident, $($variant:ident $(, $field:ident: $ty:ty)*);*) => {
+    // what an enum variant holds: the node itself, or `$payload` such as an
+    // `Rc` of it when the node is shared with the runtime
+    (@payload $variant:ident) => { $variant };
+    (@payload $variant:ident $payload:ty) => { $payload };
+    (@set_span $node:ident $span:ident) => { $node.span = $span };
+    (@set_span $node:ident $span:ident $payload:ty) => {
+        ::std::rc::Rc::make_mut(&mut $node).span = $span
+    };
+    ($st_name:ident, $($variant:ident $(($payload:ty))? $(, $field:ident: $ty:ty)*);*) => {
 
         // pub trait Visitor<Result> : Sized {
         //     $(
@@ -11,13 +19,21 @@ macro_rules! gen_struct {
 
         $(#[derive(Debug, Clone)]
         pub struct $variant {
-             $(pub $field: $ty),*
+             $(pub $field: $ty,)*
+             pub span: $crate::tokens::token::Span,
         }
 
         impl $variant {
             #[allow(dead_code)]
             pub fn new($($field: $ty),*) -> Self {
-                Self { $($field),* }
+                Self { $($field,)* span: Default::default() }
+            }
+
+            /// Attach the source range this node was parsed from.
+            #[allow(dead_code)]
+            pub fn with_span(mut self, span: $crate::tokens::token::Span) -> Self {
+                self.span = span;
+                self
             }
 
             // #[allow(dead_code)]
@@ -31,7 +47,7 @@ macro_rules! gen_struct {
             impl From<$variant> for $st_name {
 
                 fn from(value:$variant) ->Self {
-                    Self::$variant(value)
+                    Self::$variant(value.into())
                 }
             }
 
@@ -42,9 +58,29 @@ macro_rules! gen_struct {
         pub enum $st_name {
 
             $(
-                $variant($variant),
+                $variant($crate::gen_struct!(@payload $variant $($payload)?)),
             )*
         }
+
+        impl $st_name {
+            /// The source range covered by this node.
+            #[allow(dead_code)]
+            pub fn span(&self) -> $crate::tokens::token::Span {
+                match self {
+                    $($st_name::$variant(v) => v.span,)*
+                }
+            }
+
+            #[allow(dead_code)]
+            pub fn with_span(self, span: $crate::tokens::token::Span) -> Self {
+                match self {
+                    $($st_name::$variant(mut v) => {
+                        $crate::gen_struct!(@set_span v span $($payload)?);
+                        $st_name::$variant(v)
+                    })*
+                }
+            }
+        }
         // impl $st_name {
         //     #[allow(dead_code)]
         //     pub fn accept<T: Visitor<Res>, Res>(self, x: T) -> Res {
@@ -65,6 +101,9 @@ macro_rules! gen_struct {
 #[macro_export]
 macro_rules! gen_native_func {
     ($fn_name:ident, $int:ident, $body:expr  $(, $arg:ident)*  ) => {
+        $crate::gen_native_func!($fn_name as stringify!($fn_name), $int, $body $(, $arg)*);
+    };
+    ($fn_name:ident as $lox_name:expr, $int:ident, $body:expr  $(, $arg:ident)*  ) => {
 
     #[derive(Debug)]
     struct $fn_name;
@@ -76,18 +115,18 @@ macro_rules! gen_native_func {
         }
 
         fn name(&self) ->String {
-            String::from(stringify!(fn_name))
+            String::from($lox_name)
         }
         fn kind(&self) -> LoxCallableType {
             LoxCallableType::NativeFunction
         }
 
-        fn call(&mut self, interpreter:Interpreter,_args:Vec<LoxType>) -> RuntimeResult<(LoxType, Interpreter)> {
-            #[allow(unused_mut)]
-            let  $int = interpreter;
+        fn call(&self, interpreter: &mut Interpreter, _args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+            #[allow(unused_variables)]
+            let $int = interpreter;
             let mut _idx = 0;
             $( let $arg= &_args[_idx];_idx+=1; )*
-            Ok(($body,$int))
+            Ok($body)
         }
     }
     impl From<$fn_name> for LoxType {
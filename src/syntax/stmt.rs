@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::{
     gen_struct,
     tokens::token::{LoxType, Token},
@@ -7,16 +9,33 @@ use super::expr::{Expr, Literal, Variable};
 
 gen_struct!(Stmt,
     Expression, expression:Expr;
-    Function, name: Token, params: Vec<Token>, body:Vec<Stmt>;
+    Function(Rc<Function>), name: Token, params: Vec<Token>, body:Vec<Stmt>, method_kind: MethodKind;
     If, cond: Expr,then_branch:Box<Stmt>, else_branch:Option<Box<Stmt>>;
     Print, expression:Expr;
     Return, keyword: Token, value: Option<Expr>;
     Var, name: Token, initializer: Expr;
-    While, cond: Expr, body: Box<Stmt>;
+    While, cond: Expr, body: Box<Stmt>, increment: Option<Expr>;
+    Break, keyword: Token;
+    Continue, keyword: Token;
+    Throw, keyword: Token, value: Expr;
+    Import, keyword: Token, path: Token, alias: Option<Token>, names: Vec<Token>;
+    Try, body: Vec<Stmt>, catch: Option<(Token, Vec<Stmt>)>, finally: Option<Vec<Stmt>>;
     Block, statements: Vec<Stmt>;
-    Class, name: Token, superclass: Option<Variable>,  methods: Vec<Function>
+    Class, name: Token, superclass: Option<Variable>,  methods: Vec<Rc<Function>>, class_methods: Vec<Rc<Function>>
 );
 
+/// How a method declared in a class body is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MethodKind {
+    /// An ordinary function or method, called with parentheses.
+    #[default]
+    Method,
+    /// `name { }`, called when the property is read.
+    Getter,
+    /// `name=(value) { }`, called when the property is assigned.
+    Setter,
+}
+
 impl Default for Stmt {
     fn default() -> Self {
         return Stmt::Expression(Expression::new(Expr::Literal(Literal::new(
@@ -1,11 +1,22 @@
-use std::{cell::RefCell, fmt::Display, rc::Rc};
+use std::{
+    cell::RefCell,
+    fmt::{Display, Write},
+    rc::Rc,
+};
 
 use crate::{
     errors::RuntimeResult,
+    gc::Edge,
     interpreter::Interpreter,
     lox_class::{LoxClass, LoxInstance},
     lox_function::LoxFunction,
+    lox_list::LoxList,
+    lox_map::LoxMap,
+    module::LoxModule,
+    native_method::NativeMethodDef,
+    number::Number,
     tokens::token_type::TokenType,
+    vm::Object,
 };
 pub trait LoxCallable {
     fn constructor(&self) -> Option<&LoxClass> {
@@ -14,11 +25,12 @@ pub trait LoxCallable {
     fn kind(&self) -> LoxCallableType;
     fn name(&self) -> String;
     fn arity(&self) -> usize;
-    fn call(
-        &mut self,
-        interpreter: Interpreter,
-        args: Vec<LoxType>,
-    ) -> RuntimeResult<(LoxType, Interpreter)>;
+    fn call(&self, interpreter: &mut Interpreter, args: Vec<LoxType>) -> RuntimeResult<LoxType>;
+    /// Report the collectable objects the callable keeps alive.
+    fn trace(&self, _visit: &mut dyn FnMut(Edge<'_>)) {}
+    /// Drop the references the callable can change after it is created.
+    /// Only called on garbage.
+    fn clear(&self) {}
 }
 impl std::fmt::Debug for dyn LoxCallable {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -42,27 +54,47 @@ pub enum LoxCallableType {
 pub enum LoxInstanceValue {
     Free(LoxType),
     Bound(Rc<RefCell<LoxFunction>>),
+    /// A method of a native class, still to be bound to the instance.
+    Native(NativeMethodDef),
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum LoxType {
     String(String),
     Float(f64),
+    Int(i64),
     True,
     False,
     Nil,
     Callable(Rc<RefCell<dyn LoxCallable>>),
+    #[default]
     InternalNoValue,
     Data(Rc<RefCell<LoxInstance>>),
+    List(LoxList),
+    Map(LoxMap),
+    Module(Rc<LoxModule>),
+    /// A function, class or instance of the bytecode VM. Native code can
+    /// store and pass it around, but only the VM can call it or read it.
+    Vm(Object),
 }
 
 impl PartialEq for LoxType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::String(l0), Self::String(r0)) => l0 == r0,
-            (Self::Float(l0), Self::Float(r0)) => l0 == r0,
+            (Self::Float(_) | Self::Int(_), Self::Float(_) | Self::Int(_)) => {
+                self.as_number() == other.as_number()
+            }
             (LoxType::True, LoxType::True)
             | (LoxType::False, LoxType::False)
+            | (LoxType::Nil, LoxType::Nil)
             | (LoxType::InternalNoValue, LoxType::InternalNoValue) => true,
+            // everything else compares by identity
+            (Self::Callable(l0), Self::Callable(r0)) => std::ptr::addr_eq(l0.as_ptr(), r0.as_ptr()),
+            (Self::Data(l0), Self::Data(r0)) => Rc::ptr_eq(l0, r0),
+            (Self::List(l0), Self::List(r0)) => Rc::ptr_eq(l0, r0),
+            (Self::Map(l0), Self::Map(r0)) => Rc::ptr_eq(l0, r0),
+            (Self::Module(l0), Self::Module(r0)) => Rc::ptr_eq(l0, r0),
+            (Self::Vm(l0), Self::Vm(r0)) => l0 == r0,
             _ => false,
         }
     }
@@ -72,15 +104,103 @@ impl Display for LoxType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             LoxType::String(s) => write!(f, "{s}"),
-            LoxType::Float(n) => write!(f, "{n}"),
+            LoxType::Float(_) | LoxType::Int(_) => {
+                write!(f, "{}", self.as_number().expect("number"))
+            }
             LoxType::True => write!(f, "true"),
             LoxType::False => write!(f, "false"),
             LoxType::Nil => write!(f, "nil"),
             LoxType::InternalNoValue => write!(f, "(?unresolved?)"),
             LoxType::Callable(c) => write!(f, "[{:?} {}]", c.borrow().kind(), c.borrow().name()),
             LoxType::Data(inst) => write!(f, "{} {{}}", inst.borrow().this.name()),
+            LoxType::Module(module) => write!(f, "<module {}>", module.name),
+            LoxType::Vm(object) => write!(f, "{object}"),
+            LoxType::List(list) => {
+                let id = Rc::as_ptr(list) as *const ();
+                if !enter_container(id) {
+                    return write!(f, "[...]");
+                }
+                let res = (|| {
+                    f.write_char('[')?;
+                    for (i, item) in list.borrow().iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write_element(f, item)?;
+                    }
+                    f.write_char(']')
+                })();
+                leave_container(id);
+                res
+            }
+            LoxType::Map(map) => {
+                let id = Rc::as_ptr(map) as *const ();
+                if !enter_container(id) {
+                    return write!(f, "{{...}}");
+                }
+                let res = (|| {
+                    f.write_char('{')?;
+                    for (i, (key, value)) in map.borrow().iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write_element(f, key)?;
+                        write!(f, ": ")?;
+                        write_element(f, value)?;
+                    }
+                    f.write_char('}')
+                })();
+                leave_container(id);
+                res
+            }
+        }
+    }
+}
+
+impl LoxType {
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            LoxType::Int(i) => Some(Number::Int(*i)),
+            LoxType::Float(f) => Some(Number::Float(*f)),
+            _ => None,
         }
     }
+
+    /// Like `to_string`, but strings are quoted so that `["a, b"]` and
+    /// `["a", "b"]` print differently.
+    pub fn repr(&self) -> String {
+        match self {
+            LoxType::String(s) => format!("{s:?}"),
+            value => value.to_string(),
+        }
+    }
+}
+
+fn write_element(f: &mut std::fmt::Formatter<'_>, value: &LoxType) -> std::fmt::Result {
+    match value {
+        LoxType::String(_) => f.write_str(&value.repr()),
+        value => write!(f, "{value}"),
+    }
+}
+
+thread_local! {
+    /// Containers currently being printed, to cut off self references.
+    static PRINTING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+}
+
+fn enter_container(id: *const ()) -> bool {
+    PRINTING.with_borrow_mut(|stack| {
+        if stack.contains(&id) {
+            false
+        } else {
+            stack.push(id);
+            true
+        }
+    })
+}
+
+fn leave_container(id: *const ()) {
+    PRINTING.with_borrow_mut(|stack| stack.retain(|&other| other != id));
 }
 impl From<bool> for LoxType {
     fn from(value: bool) -> Self {
@@ -94,9 +214,87 @@ impl From<f64> for LoxType {
     }
 }
 
-impl Default for LoxType {
-    fn default() -> Self {
-        LoxType::InternalNoValue
+impl From<i64> for LoxType {
+    fn from(value: i64) -> Self {
+        LoxType::Int(value)
+    }
+}
+
+impl From<usize> for LoxType {
+    fn from(value: usize) -> Self {
+        LoxType::Int(value as i64)
+    }
+}
+
+impl From<Number> for LoxType {
+    fn from(value: Number) -> Self {
+        match value {
+            Number::Int(i) => LoxType::Int(i),
+            Number::Float(f) => LoxType::Float(f),
+        }
+    }
+}
+
+impl From<String> for LoxType {
+    fn from(value: String) -> Self {
+        LoxType::String(value)
+    }
+}
+
+impl From<&str> for LoxType {
+    fn from(value: &str) -> Self {
+        LoxType::String(value.to_owned())
+    }
+}
+
+/// A region of the source text.
+///
+/// `start` and `end` are byte offsets, `line` and `column` are 1-based and
+/// describe where the region starts. A line of `0` means the position is
+/// unknown, for example for tokens synthesized by native code. `source` is
+/// `0` for the main program and identifies the file of an imported module
+/// otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: u32,
+    pub source: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column: column as u32,
+            source: 0,
+        }
+    }
+
+    pub fn with_source(self, source: u32) -> Self {
+        Self { source, ..self }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.line != 0
+    }
+
+    /// A span covering both `self` and `other`, which must come later in the
+    /// source.
+    pub fn to(self, other: Span) -> Span {
+        if !self.is_known() {
+            return other;
+        }
+        if !other.is_known() {
+            return self;
+        }
+        Span {
+            end: self.end.max(other.end),
+            ..self
+        }
     }
 }
 
@@ -106,15 +304,17 @@ pub struct Token {
     pub lexeme: String,
     pub literal: LoxType,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(ty: TokenType, lexeme: String, literal: LoxType, line: usize) -> Self {
+    pub fn new(ty: TokenType, lexeme: String, literal: LoxType, span: Span) -> Self {
         Token {
             ty,
             lexeme,
             literal,
-            line,
+            line: span.line,
+            span,
         }
     }
     pub fn dummy_this() -> Self {
@@ -127,6 +327,7 @@ impl Token {
             lexeme: x.into(),
             literal: LoxType::Nil,
             line: 0,
+            span: Default::default(),
         }
     }
 }
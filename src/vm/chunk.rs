@@ -0,0 +1,106 @@
+use super::value::Value;
+use crate::tokens::token::Span;
+
+/// A single VM instruction. Operands are indices into the constant table,
+/// stack slots, upvalue slots or jump distances.
+#[derive(Debug, Clone, Copy)]
+pub enum OpCode {
+    Constant(u16),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(u16),
+    SetLocal(u16),
+    GetGlobal(u16),
+    DefineGlobal(u16),
+    SetGlobal(u16),
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    GetProperty(u16),
+    SetProperty(u16),
+    GetSuper(u16),
+    /// Build a list from the given number of values on the stack.
+    BuildList(u16),
+    /// Build a map from the given number of key-value pairs on the stack.
+    BuildMap(u16),
+    GetIndex,
+    SetIndex,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Negate,
+    BitNot,
+    Print,
+    Jump(u16),
+    JumpIfFalse(u16),
+    Loop(u16),
+    Call(u8),
+    Invoke(u16, u8),
+    Closure(u16),
+    CloseUpvalue,
+    Return,
+    Class(u16),
+    Inherit,
+    Method(u16),
+    /// Add the closure on top of the stack to the class below it as a class
+    /// method.
+    ClassMethod(u16),
+    Throw,
+    /// Install a handler for errors raised before the matching `PopHandler`,
+    /// at the given distance ahead. The handler starts with the error on
+    /// top of the stack.
+    PushHandler(u16),
+    PopHandler,
+    /// Replace the error on top of the stack with the value `catch` binds.
+    Catch,
+    /// Raise the error on top of the stack again once `finally` has run.
+    Rethrow,
+    /// Load the module at the path in the given constant, running it the
+    /// first time, and push it.
+    Import(u16),
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    /// Append an instruction and return its offset.
+    pub fn write(&mut self, op: OpCode, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> Option<u16> {
+        if let Value::String(s) = &value {
+            let existing = self
+                .constants
+                .iter()
+                .position(|c| matches!(c, Value::String(c) if c == s));
+            if let Some(idx) = existing {
+                return u16::try_from(idx).ok();
+            }
+        }
+        self.constants.push(value);
+        u16::try_from(self.constants.len() - 1).ok()
+    }
+}
@@ -0,0 +1,812 @@
+use std::rc::Rc;
+
+use crate::{
+    errors::{CompileError, CompileResult},
+    lox_class::setter_name,
+    syntax::{
+        expr::Expr,
+        stmt::{self, MethodKind, Stmt},
+    },
+    tokens::{
+        token::{LoxType, Span, Token},
+        token_type::TokenType,
+    },
+};
+
+use super::{
+    chunk::OpCode,
+    value::{Function, UpvalueDesc, Value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug)]
+struct Local {
+    name: String,
+    depth: usize,
+    is_captured: bool,
+}
+
+#[derive(Debug)]
+struct FunctionState<'a> {
+    function: Function,
+    kind: FunctionType,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    tries: Vec<TryBlock<'a>>,
+}
+
+/// An enclosing loop, with the jumps that `break` and `continue` left to be
+/// patched once the loop is compiled.
+#[derive(Debug)]
+struct Loop {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+/// An enclosing `try` block. Its handler has to be dropped and its `finally`
+/// block run before a `return`, `break` or `continue` jumps out of it.
+#[derive(Debug, Clone, Copy)]
+struct TryBlock<'a> {
+    finally: Option<&'a [Stmt]>,
+    /// How many loops enclosed the block, to tell which ones it is inside.
+    loops: usize,
+}
+
+impl FunctionState<'_> {
+    fn new(name: String, kind: FunctionType) -> Self {
+        // slot 0 holds the callee, or the receiver for methods
+        let slot_zero = match kind {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            _ => "",
+        };
+        Self {
+            function: Function {
+                name,
+                top_level: kind == FunctionType::Script,
+                ..Default::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: slot_zero.into(),
+                depth: 0,
+                is_captured: false,
+            }],
+            scope_depth: 0,
+            loops: Vec::new(),
+            tries: Vec::new(),
+        }
+    }
+}
+
+/// Compiles a resolved syntax tree into bytecode for the [`super::Vm`].
+///
+/// Top level declarations become globals, everything else lives in stack
+/// slots. Variables captured by closures are turned into upvalues.
+pub struct Compiler<'a> {
+    states: Vec<FunctionState<'a>>,
+    classes: Vec<bool>,
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new() -> Self {
+        Self {
+            states: vec![FunctionState::new("script".into(), FunctionType::Script)],
+            classes: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    /// Compile a whole script. If the last statement is an expression its
+    /// value is returned from the script function, the way the REPL expects.
+    pub fn compile(mut self, stmts: &'a [Stmt]) -> CompileResult<(Function, bool)> {
+        let (last, rest) = match stmts.split_last() {
+            Some((Stmt::Expression(e), rest)) => (Some(&e.expression), rest),
+            _ => (None, stmts),
+        };
+        for stmt in rest {
+            self.statement(stmt)?;
+        }
+        match last {
+            Some(e) => self.expression(e)?,
+            None => {
+                self.emit(OpCode::Nil);
+            }
+        };
+        self.emit(OpCode::Return);
+        let state = self.states.pop().expect("script state");
+        Ok((state.function, last.is_some()))
+    }
+
+    fn current(&mut self) -> &mut FunctionState<'a> {
+        self.states.last_mut().expect("compiler state")
+    }
+
+    fn emit(&mut self, op: OpCode) -> usize {
+        let span = self.span;
+        self.current().function.chunk.write(op, span)
+    }
+
+    fn error<T, M: Into<String>>(&self, message: M) -> CompileResult<T> {
+        Err(CompileError::at(message, self.span))
+    }
+
+    fn make_constant(&mut self, value: Value) -> CompileResult<u16> {
+        match self.current().function.chunk.add_constant(value) {
+            Some(idx) => Ok(idx),
+            None => self.error("Too many constants in one chunk."),
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> CompileResult<u16> {
+        self.make_constant(Value::String(name.lexeme.as_str().into()))
+    }
+
+    fn emit_constant(&mut self, value: Value) -> CompileResult<()> {
+        let idx = self.make_constant(value)?;
+        self.emit(OpCode::Constant(idx));
+        Ok(())
+    }
+
+    fn emit_jump(&mut self, op: fn(u16) -> OpCode) -> usize {
+        self.emit(op(u16::MAX))
+    }
+
+    fn patch_jump(&mut self, at: usize) -> CompileResult<()> {
+        let chunk = &mut self.current().function.chunk;
+        let Ok(dist) = u16::try_from(chunk.code.len() - at - 1) else {
+            return self.error("Too much code to jump over.");
+        };
+        chunk.code[at] = match chunk.code[at] {
+            OpCode::Jump(_) => OpCode::Jump(dist),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(dist),
+            OpCode::PushHandler(_) => OpCode::PushHandler(dist),
+            op => unreachable!("cannot patch {op:?}"),
+        };
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize) -> CompileResult<()> {
+        let len = self.current().function.chunk.code.len();
+        match u16::try_from(len - start + 1) {
+            Ok(dist) => {
+                self.emit(OpCode::Loop(dist));
+                Ok(())
+            }
+            Err(_) => self.error("Loop body too large."),
+        }
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_return_value();
+        self.emit(OpCode::Return);
+    }
+
+    /// Push what a `return` without a value returns.
+    fn emit_return_value(&mut self) {
+        if self.current().kind == FunctionType::Initializer {
+            self.emit(OpCode::GetLocal(0));
+        } else {
+            self.emit(OpCode::Nil);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        loop {
+            let state = self.current();
+            match state.locals.last() {
+                Some(local) if local.depth > state.scope_depth => {
+                    let op = if local.is_captured {
+                        OpCode::CloseUpvalue
+                    } else {
+                        OpCode::Pop
+                    };
+                    state.locals.pop();
+                    self.emit(op);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Discard the locals declared inside the innermost loop and emit a jump
+    /// out of it, returning the jump to patch.
+    fn loop_jump(&mut self) -> CompileResult<usize> {
+        let Some(depth) = self.current().loops.last().map(|lp| lp.scope_depth) else {
+            return self.error("Can't jump outside of a loop.");
+        };
+        let loops = self.current().loops.len();
+        let inside = self.current().tries.iter().position(|t| t.loops == loops);
+        if let Some(from) = inside {
+            self.exit_tries(from)?;
+        }
+        let state = self.current();
+        let ops = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| {
+                if local.is_captured {
+                    OpCode::CloseUpvalue
+                } else {
+                    OpCode::Pop
+                }
+            })
+            .collect::<Vec<_>>();
+        for op in ops {
+            self.emit(op);
+        }
+        Ok(self.emit_jump(OpCode::Jump))
+    }
+
+    /// Drop the handlers of the `try` blocks from `from` on and inline their
+    /// `finally` blocks, innermost first, for a jump out of them.
+    fn exit_tries(&mut self, from: usize) -> CompileResult<()> {
+        let tries = self.current().tries.split_off(from);
+        for (idx, block) in tries.iter().enumerate().rev() {
+            // a jump out of this `finally` leaves only the blocks around it
+            self.current().tries.extend_from_slice(&tries[..idx]);
+            self.emit(OpCode::PopHandler);
+            if let Some(finally) = block.finally {
+                self.block(finally)?;
+            }
+            self.current().tries.truncate(from);
+        }
+        self.current().tries.extend(tries);
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &str) -> CompileResult<()> {
+        if self.current().locals.len() > u16::MAX as usize {
+            return self.error("Too many local variables in function.");
+        }
+        let depth = self.current().scope_depth;
+        self.current().locals.push(Local {
+            name: name.into(),
+            depth,
+            is_captured: false,
+        });
+        Ok(())
+    }
+
+    /// Bind the value on top of the stack to `name`, either as a new local
+    /// or as a global when declared at the top level.
+    fn define_variable(&mut self, name: &Token) -> CompileResult<()> {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme)
+        } else {
+            let idx = self.identifier_constant(name)?;
+            self.emit(OpCode::DefineGlobal(idx));
+            Ok(())
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u16> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|idx| idx as u16)
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u16, is_local: bool) -> CompileResult<u16> {
+        let upvalues = &mut self.states[state].function.upvalues;
+        if let Some(idx) = upvalues
+            .iter()
+            .position(|u| u.index == index && u.is_local == is_local)
+        {
+            return Ok(idx as u16);
+        }
+        if upvalues.len() >= u16::MAX as usize {
+            return self.error("Too many closure variables in function.");
+        }
+        upvalues.push(UpvalueDesc { index, is_local });
+        Ok((upvalues.len() - 1) as u16)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &str) -> CompileResult<Option<u16>> {
+        if state == 0 {
+            return Ok(None);
+        }
+        if let Some(local) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[local as usize].is_captured = true;
+            return self.add_upvalue(state, local, true).map(Some);
+        }
+        match self.resolve_upvalue(state - 1, name)? {
+            Some(upvalue) => self.add_upvalue(state, upvalue, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn named_variable(&mut self, name: &Token, assign: bool) -> CompileResult<()> {
+        let state = self.states.len() - 1;
+        let op = if let Some(slot) = self.resolve_local(state, &name.lexeme) {
+            if assign {
+                OpCode::SetLocal(slot)
+            } else {
+                OpCode::GetLocal(slot)
+            }
+        } else if let Some(slot) = self.resolve_upvalue(state, &name.lexeme)? {
+            if assign {
+                OpCode::SetUpvalue(slot)
+            } else {
+                OpCode::GetUpvalue(slot)
+            }
+        } else {
+            let idx = self.identifier_constant(name)?;
+            if assign {
+                OpCode::SetGlobal(idx)
+            } else {
+                OpCode::GetGlobal(idx)
+            }
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> CompileResult<()> {
+        match stmt {
+            Stmt::Expression(e) => {
+                self.expression(&e.expression)?;
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Print(e) => {
+                self.expression(&e.expression)?;
+                self.emit(OpCode::Print);
+            }
+            Stmt::Var(e) => {
+                self.span = e.name.span;
+                self.expression(&e.initializer)?;
+                self.define_variable(&e.name)?;
+            }
+            Stmt::Block(e) => self.block(&e.statements)?,
+            Stmt::If(e) => {
+                self.expression(&e.cond)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(&e.then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(else_branch) = &e.else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::While(e) => {
+                let start = self.current().function.chunk.code.len();
+                self.expression(&e.cond)?;
+                let exit = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                let scope_depth = self.current().scope_depth;
+                self.current().loops.push(Loop {
+                    scope_depth,
+                    breaks: Vec::new(),
+                    continues: Vec::new(),
+                });
+                self.statement(&e.body)?;
+                let lp = self.current().loops.pop().expect("loop state");
+                for jump in lp.continues {
+                    self.patch_jump(jump)?;
+                }
+                if let Some(increment) = &e.increment {
+                    self.expression(increment)?;
+                    self.emit(OpCode::Pop);
+                }
+                self.emit_loop(start)?;
+                self.patch_jump(exit)?;
+                self.emit(OpCode::Pop);
+                for jump in lp.breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+            Stmt::Throw(e) => {
+                self.expression(&e.value)?;
+                self.span = e.span;
+                self.emit(OpCode::Throw);
+            }
+            Stmt::Try(e) => self.try_statement(e)?,
+            Stmt::Import(e) => {
+                let LoxType::String(path) = &e.path.literal else {
+                    unreachable!("module paths are string literals")
+                };
+                let path = self.make_constant(Value::String(path.as_str().into()))?;
+                if let Some(alias) = &e.alias {
+                    self.span = e.path.span;
+                    self.emit(OpCode::Import(path));
+                    self.define_variable(alias)?;
+                }
+                // the module is cached, so loading it again for each name is cheap
+                for name in &e.names {
+                    self.span = e.path.span;
+                    self.emit(OpCode::Import(path));
+                    self.span = name.span;
+                    let member = self.identifier_constant(name)?;
+                    self.emit(OpCode::GetProperty(member));
+                    self.define_variable(name)?;
+                }
+            }
+            Stmt::Break(e) => {
+                self.span = e.keyword.span;
+                let jump = self.loop_jump()?;
+                self.current()
+                    .loops
+                    .last_mut()
+                    .expect("loop")
+                    .breaks
+                    .push(jump);
+            }
+            Stmt::Continue(e) => {
+                self.span = e.keyword.span;
+                let jump = self.loop_jump()?;
+                self.current()
+                    .loops
+                    .last_mut()
+                    .expect("loop")
+                    .continues
+                    .push(jump);
+            }
+            Stmt::Function(e) => {
+                self.span = e.name.span;
+                if self.current().scope_depth > 0 {
+                    // declare first so the body can refer to itself
+                    self.add_local(&e.name.lexeme)?;
+                    self.function(e, FunctionType::Function)?;
+                } else {
+                    self.function(e, FunctionType::Function)?;
+                    self.define_variable(&e.name)?;
+                }
+            }
+            Stmt::Return(e) => {
+                self.span = e.keyword.span;
+                match &e.value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_return_value(),
+                }
+                if !self.current().tries.is_empty() {
+                    // the result waits in a slot of its own while `finally` runs
+                    self.add_local("")?;
+                    self.exit_tries(0)?;
+                    self.current().locals.pop();
+                }
+                self.emit(OpCode::Return);
+            }
+            Stmt::Class(cls) => self.class(cls)?,
+        };
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt]) -> CompileResult<()> {
+        self.begin_scope();
+        for stmt in stmts {
+            self.statement(stmt)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Compile `body` with a handler for the errors raised in it and return
+    /// the jump to patch to the handler's code.
+    fn guarded(
+        &mut self,
+        finally: Option<&'a [Stmt]>,
+        body: impl FnOnce(&mut Self) -> CompileResult<()>,
+    ) -> CompileResult<usize> {
+        let handler = self.emit_jump(OpCode::PushHandler);
+        let loops = self.current().loops.len();
+        self.current().tries.push(TryBlock { finally, loops });
+        body(self)?;
+        self.current().tries.pop();
+        self.emit(OpCode::PopHandler);
+        Ok(handler)
+    }
+
+    /// A `try` with both `catch` and `finally` runs as a `try`-`catch`
+    /// wrapped in a `try`-`finally`.
+    fn try_statement(&mut self, e: &'a stmt::Try) -> CompileResult<()> {
+        self.span = e.span;
+        let Some(finally) = e.finally.as_deref() else {
+            return self.try_catch(e);
+        };
+        let handler = self.guarded(Some(finally), |this| match e.catch {
+            Some(_) => this.try_catch(e),
+            None => this.block(&e.body),
+        })?;
+        self.block(finally)?;
+        let end = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(handler)?;
+        self.begin_scope();
+        self.add_local("")?;
+        self.block(finally)?;
+        self.span = e.span;
+        self.emit(OpCode::Rethrow);
+        // `Rethrow` already took the error off the stack
+        self.current().locals.pop();
+        self.current().scope_depth -= 1;
+        self.patch_jump(end)
+    }
+
+    fn try_catch(&mut self, e: &'a stmt::Try) -> CompileResult<()> {
+        let Some((name, body)) = &e.catch else {
+            unreachable!("try without catch or finally")
+        };
+        let handler = self.guarded(None, |this| this.block(&e.body))?;
+        let end = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(handler)?;
+        self.span = name.span;
+        self.emit(OpCode::Catch);
+        self.begin_scope();
+        self.add_local(&name.lexeme)?;
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        self.end_scope();
+        self.patch_jump(end)
+    }
+
+    fn class(&mut self, cls: &'a stmt::Class) -> CompileResult<()> {
+        self.span = cls.name.span;
+        let name = self.identifier_constant(&cls.name)?;
+        self.emit(OpCode::Class(name));
+        self.define_variable(&cls.name)?;
+
+        self.classes.push(cls.superclass.is_some());
+        if let Some(superclass) = &cls.superclass {
+            self.named_variable(&superclass.name, false)?;
+            self.begin_scope();
+            self.add_local("super")?;
+            self.named_variable(&cls.name, false)?;
+            self.emit(OpCode::Inherit);
+        }
+
+        self.named_variable(&cls.name, false)?;
+        for method in &cls.methods {
+            self.span = method.name.span;
+            let name = match method.method_kind {
+                MethodKind::Setter => setter_name(&method.name.lexeme),
+                _ => method.name.lexeme.clone(),
+            };
+            let kind = if name == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(method, kind)?;
+            let name = self.make_constant(Value::String(name.into()))?;
+            self.emit(OpCode::Method(name));
+        }
+        for method in &cls.class_methods {
+            self.span = method.name.span;
+            self.function(method, FunctionType::Method)?;
+            let name = self.identifier_constant(&method.name)?;
+            self.emit(OpCode::ClassMethod(name));
+        }
+        self.emit(OpCode::Pop);
+
+        if cls.superclass.is_some() {
+            self.end_scope();
+        }
+        self.classes.pop();
+        Ok(())
+    }
+
+    fn function(&mut self, fun: &'a stmt::Function, kind: FunctionType) -> CompileResult<()> {
+        self.states
+            .push(FunctionState::new(fun.name.lexeme.clone(), kind));
+        self.begin_scope();
+        self.current().function.arity = fun.params.len();
+        self.current().function.getter = fun.method_kind == MethodKind::Getter;
+        for param in &fun.params {
+            self.add_local(&param.lexeme)?;
+        }
+        for stmt in &fun.body {
+            self.statement(stmt)?;
+        }
+        self.emit_return();
+
+        let state = self.states.pop().expect("function state");
+        let idx = self.make_constant(Value::Function(Rc::new(state.function)))?;
+        self.emit(OpCode::Closure(idx));
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &'a Expr) -> CompileResult<()> {
+        match expr {
+            Expr::Literal(e) => match &e.value {
+                LoxType::Nil | LoxType::InternalNoValue => {
+                    self.emit(OpCode::Nil);
+                }
+                LoxType::True => {
+                    self.emit(OpCode::True);
+                }
+                LoxType::False => {
+                    self.emit(OpCode::False);
+                }
+                v => self.emit_constant(v.clone().into())?,
+            },
+            Expr::Grouping(e) => self.expression(&e.expression)?,
+            Expr::Unary(e) => {
+                self.expression(&e.right)?;
+                self.span = e.span;
+                match e.operator.ty {
+                    TokenType::Minus => self.emit(OpCode::Negate),
+                    TokenType::Bang => self.emit(OpCode::Not),
+                    TokenType::Tilde => self.emit(OpCode::BitNot),
+                    _ => {
+                        return self.error(format!("Unknown unary operator {}", e.operator.lexeme))
+                    }
+                };
+            }
+            Expr::Binary(e) => {
+                self.expression(&e.left)?;
+                self.expression(&e.right)?;
+                self.span = e.span;
+                let op = match e.operator.ty {
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Star => OpCode::Multiply,
+                    TokenType::Slash => OpCode::Divide,
+                    TokenType::TildeSlash => OpCode::IntDivide,
+                    TokenType::Percent => OpCode::Modulo,
+                    TokenType::Ampersand => OpCode::BitAnd,
+                    TokenType::Pipe => OpCode::BitOr,
+                    TokenType::Caret => OpCode::BitXor,
+                    TokenType::LessLess => OpCode::ShiftLeft,
+                    TokenType::GreaterGreater => OpCode::ShiftRight,
+                    TokenType::EqualEqual => OpCode::Equal,
+                    // `a != b` is `!(a == b)` so that `__eq__` covers both
+                    TokenType::BangEqual => {
+                        self.emit(OpCode::Equal);
+                        OpCode::Not
+                    }
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    _ => {
+                        return self.error(format!("Unknown binary operator {}", e.operator.lexeme))
+                    }
+                };
+                self.emit(op);
+            }
+            Expr::Logical(e) => {
+                self.expression(&e.left)?;
+                self.span = e.operator.span;
+                if e.operator.ty == TokenType::And {
+                    let end = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit(OpCode::Pop);
+                    self.expression(&e.right)?;
+                    self.patch_jump(end)?;
+                } else {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit(OpCode::Pop);
+                    self.expression(&e.right)?;
+                    self.patch_jump(end)?;
+                }
+            }
+            Expr::Variable(e) => {
+                self.span = e.name.span;
+                self.named_variable(&e.name, false)?;
+            }
+            Expr::Assign(e) => {
+                self.expression(&e.value)?;
+                self.span = e.name.span;
+                self.named_variable(&e.name, true)?;
+            }
+            Expr::Call(e) => {
+                let argc = match u8::try_from(e.args.len()) {
+                    Ok(argc) => argc,
+                    Err(_) => return self.error("Can't have more than 255 arguments"),
+                };
+                if let Expr::Get(get) = e.callee.as_ref() {
+                    self.expression(&get.object)?;
+                    for arg in &e.args {
+                        self.expression(arg)?;
+                    }
+                    self.span = e.span;
+                    let name = self.identifier_constant(&get.name)?;
+                    self.emit(OpCode::Invoke(name, argc));
+                } else {
+                    self.expression(&e.callee)?;
+                    for arg in &e.args {
+                        self.expression(arg)?;
+                    }
+                    self.span = e.span;
+                    self.emit(OpCode::Call(argc));
+                }
+            }
+            Expr::Get(e) => {
+                self.expression(&e.object)?;
+                self.span = e.name.span;
+                let name = self.identifier_constant(&e.name)?;
+                self.emit(OpCode::GetProperty(name));
+            }
+            Expr::Set(e) => {
+                self.expression(&e.object)?;
+                self.expression(&e.value)?;
+                self.span = e.name.span;
+                let name = self.identifier_constant(&e.name)?;
+                self.emit(OpCode::SetProperty(name));
+            }
+            Expr::This(e) => {
+                self.span = e.keyword.span;
+                if self.classes.is_empty() {
+                    return self.error("Cannot use 'this' outside of a class.");
+                }
+                self.named_variable(&e.keyword, false)?;
+            }
+            Expr::Super(e) => {
+                self.span = e.keyword.span;
+                match self.classes.last() {
+                    None => return self.error("Can't use 'super' outside of a class."),
+                    Some(false) => return self.error("Can't use 'super' without a superclass."),
+                    Some(true) => (),
+                }
+                self.named_variable(&Token::dummy_this(), false)?;
+                self.named_variable(&e.keyword, false)?;
+                let name = self.identifier_constant(&e.method)?;
+                self.emit(OpCode::GetSuper(name));
+            }
+            Expr::List(e) => {
+                self.span = e.span;
+                let Ok(len) = u16::try_from(e.elements.len()) else {
+                    return self.error("Too many elements in a list literal.");
+                };
+                for element in &e.elements {
+                    self.expression(element)?;
+                }
+                self.span = e.span;
+                self.emit(OpCode::BuildList(len));
+            }
+            Expr::Map(e) => {
+                self.span = e.span;
+                let Ok(len) = u16::try_from(e.entries.len()) else {
+                    return self.error("Too many entries in a map literal.");
+                };
+                for (key, value) in &e.entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.span = e.span;
+                self.emit(OpCode::BuildMap(len));
+            }
+            Expr::Lambda(e) => {
+                self.span = e.span;
+                self.function(&e.function, FunctionType::Function)?;
+            }
+            Expr::Index(e) => {
+                self.expression(&e.object)?;
+                self.expression(&e.index)?;
+                self.span = e.index.span();
+                self.emit(OpCode::GetIndex);
+            }
+            Expr::SetIndex(e) => {
+                self.expression(&e.object)?;
+                self.expression(&e.index)?;
+                self.expression(&e.value)?;
+                self.span = e.index.span();
+                self.emit(OpCode::SetIndex);
+            }
+        };
+        Ok(())
+    }
+}
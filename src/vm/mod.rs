@@ -0,0 +1,1086 @@
+//! A bytecode backend for Lox.
+//!
+//! The [`Compiler`] turns the resolved syntax tree into a compact chunk of
+//! [`OpCode`]s and the [`Vm`] runs it on a value stack, capturing closed
+//! over variables as upvalues. Native functions and classes are still
+//! provided by the tree-walking runtime and are called through the
+//! [`Interpreter`] the VM owns.
+
+mod chunk;
+mod compiler;
+mod value;
+
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub use chunk::{Chunk, OpCode};
+pub use compiler::Compiler;
+pub use value::{Closure, Function, Object, Value};
+
+use crate::{
+    embed::parse_program,
+    errors::{Diagnostic, InterruptKind, RuntimeResult, StackFrame, VmError, VmResult},
+    gc,
+    interpreter::{stack_trace, uncaught_message, Interpreter},
+    lox_class::{operator_method, setter_name, static_member, LoxInstance, EQ_METHODS, NEG_METHOD},
+    lox_list::{self, get_index, set_index},
+    lox_map::{self, MapStore},
+    lox_string,
+    module::{load_error, module_name},
+    number::NumOp,
+    tokens::{
+        token::{LoxCallableType, LoxInstanceValue, LoxType, Span, Token},
+        token_type::TokenType,
+    },
+};
+
+use value::{BoundMethod, Class, Globals, Instance, Module, StaticMember, Upvalue};
+
+const FRAMES_MAX: usize = 1024;
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize,
+}
+
+/// A `try` block being run: where to resume when an error is raised in it,
+/// and how much of the frame and value stacks to keep.
+struct Handler {
+    frames: usize,
+    stack: usize,
+    ip: usize,
+}
+
+pub struct Vm {
+    pub interpreter: Interpreter,
+    /// Globals of the main script. Modules have their own.
+    globals: Globals,
+    /// Modules that ran to completion, by file.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    /// Whether `interpreter.call_stack` still mirrors `frames` for natives
+    /// that read it, such as `Error.init`.
+    call_stack_synced: bool,
+}
+
+impl Vm {
+    pub fn new(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter,
+            globals: Default::default(),
+            modules: HashMap::new(),
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            open_upvalues: Vec::new(),
+            handlers: Vec::new(),
+            call_stack_synced: false,
+        }
+    }
+
+    /// Look up a global of the main script.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.lookup_global(&self.globals, name)
+    }
+
+    pub fn set_global<T: Into<String>>(&mut self, name: T, value: Value) {
+        self.globals.borrow_mut().insert(name.into(), value);
+    }
+
+    /// Look up a global in `globals`, falling back to the natives every
+    /// script and module starts with.
+    fn lookup_global(&self, globals: &Globals, name: &str) -> Option<Value> {
+        match globals.borrow().get(name) {
+            Some(v) => Some(v.clone()),
+            None => self
+                .interpreter
+                .builtins
+                .get(&Token::dummy(name, TokenType::Identifier))
+                .ok()
+                .map(Value::from),
+        }
+    }
+
+    /// Globals of the script or module the running function belongs to.
+    fn frame_globals(&self) -> Globals {
+        let frame = self.frames.last().expect("call frame");
+        Rc::clone(&frame.closure.globals)
+    }
+
+    /// Run a compiled script and return the value it produced.
+    pub fn interpret(&mut self, function: Function) -> VmResult<Value> {
+        let closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: Vec::new(),
+            globals: Rc::clone(&self.globals),
+        });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        if let Err(err) = self.call(closure, 0) {
+            self.stack.clear();
+            return Err(err);
+        }
+        self.run(0)
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("call frame")
+    }
+
+    fn span(&self) -> Span {
+        match self.frames.last() {
+            Some(frame) => frame.closure.function.chunk.spans[frame.ip.saturating_sub(1)],
+            None => Span::default(),
+        }
+    }
+
+    /// The active calls, innermost first, leaving out the top level of the
+    /// script and of the modules being imported.
+    fn trace(&self) -> Vec<StackFrame> {
+        self.frames
+            .iter()
+            .filter(|frame| !frame.closure.function.top_level)
+            .rev()
+            .map(|frame| {
+                let function = &frame.closure.function;
+                let line = function.chunk.spans[frame.ip.saturating_sub(1)].line;
+                StackFrame::new(function.name.clone(), line)
+            })
+            .collect()
+    }
+
+    fn error<T, M: Into<String>>(&self, message: M) -> VmResult<T> {
+        let mut err = VmError::at(message, self.span());
+        err.trace = self.trace();
+        Err(err)
+    }
+
+    fn constant(&self, idx: u16) -> &Value {
+        let frame = self.frames.last().expect("call frame");
+        &frame.closure.function.chunk.constants[idx as usize]
+    }
+
+    fn constant_str(&self, idx: u16) -> Rc<str> {
+        match self.constant(idx) {
+            Value::String(s) => Rc::clone(s),
+            v => panic!("expected a string constant, found {v}"),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    /// Truthiness of `val`, honouring the interpreter's `nil_is_truthy`.
+    fn is_falsey(&self, val: &Value) -> bool {
+        val.is_falsey() && !(self.interpreter.nil_is_truthy && matches!(val, Value::Nil))
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// Run until the frames above `depth` have returned, resuming at the
+    /// innermost handler of those frames when an error is raised.
+    fn run(&mut self, depth: usize) -> VmResult<Value> {
+        loop {
+            match self.dispatch(depth) {
+                Ok(val) => return Ok(val),
+                Err(err) => self.unwind(err, depth)?,
+            }
+        }
+    }
+
+    /// Pass `err` to the innermost handler of a frame above `depth`, or clean
+    /// up those frames and give it back when there is none.
+    fn unwind(&mut self, err: VmError, depth: usize) -> VmResult<()> {
+        let handled = matches!(
+            err.interrupt_kind,
+            InterruptKind::Builtin | InterruptKind::Throw(_)
+        );
+        match self.handlers.last() {
+            Some(handler) if handled && handler.frames > depth => {
+                let handler = self.handlers.pop().expect("handler");
+                self.frames.truncate(handler.frames);
+                self.call_stack_synced = false;
+                self.close_upvalues(handler.stack);
+                self.stack.truncate(handler.stack);
+                self.frame().ip = handler.ip;
+                self.stack.push(Value::Error(Rc::new(err)));
+                Ok(())
+            }
+            _ => {
+                let base = self.frames.get(depth).map_or(0, |frame| frame.base);
+                self.handlers.retain(|handler| handler.frames <= depth);
+                self.frames.truncate(depth);
+                self.call_stack_synced = false;
+                self.close_upvalues(base);
+                self.stack.truncate(base);
+                Err(err)
+            }
+        }
+    }
+
+    fn dispatch(&mut self, depth: usize) -> VmResult<Value> {
+        loop {
+            let op = {
+                let frame = self.frame();
+                let op = frame.closure.function.chunk.code[frame.ip];
+                frame.ip += 1;
+                op
+            };
+            match op {
+                OpCode::Constant(idx) => {
+                    let val = self.constant(idx).clone();
+                    self.stack.push(val);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal(slot) => {
+                    let base = self.frame().base;
+                    let val = self.stack[base + slot as usize].clone();
+                    self.stack.push(val);
+                }
+                OpCode::SetLocal(slot) => {
+                    let base = self.frame().base;
+                    self.stack[base + slot as usize] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal(idx) => {
+                    let name = self.constant_str(idx);
+                    match self.lookup_global(&self.frame_globals(), &name) {
+                        Some(val) => self.stack.push(val),
+                        None => return self.error(format!("Undefined variable '{name}'")),
+                    }
+                }
+                OpCode::DefineGlobal(idx) => {
+                    let name = self.constant_str(idx);
+                    let val = self.pop();
+                    self.frame_globals()
+                        .borrow_mut()
+                        .insert(name.to_string(), val);
+                }
+                OpCode::SetGlobal(idx) => {
+                    let name = self.constant_str(idx);
+                    let globals = self.frame_globals();
+                    if self.lookup_global(&globals, &name).is_none() {
+                        return self.error(format!("Undefined variable '{name}'"));
+                    }
+                    let val = self.peek(0).clone();
+                    globals.borrow_mut().insert(name.to_string(), val);
+                }
+                OpCode::GetUpvalue(slot) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[slot as usize]);
+                    let val = match &*upvalue.borrow() {
+                        Upvalue::Open(idx) => self.stack[*idx].clone(),
+                        Upvalue::Closed(val) => val.clone(),
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::SetUpvalue(slot) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[slot as usize]);
+                    let val = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(idx) => self.stack[*idx] = val,
+                        Upvalue::Closed(closed) => *closed = val,
+                    };
+                }
+                OpCode::GetProperty(idx) => {
+                    let name = self.constant_str(idx);
+                    let obj = self.pop();
+                    let val = self.get_property(obj, &name)?;
+                    self.stack.push(val);
+                }
+                OpCode::SetProperty(idx) => {
+                    let name = self.constant_str(idx);
+                    let val = self.pop();
+                    let obj = self.pop();
+                    match obj {
+                        Value::Instance(inst) => {
+                            let (setter, getter) = {
+                                let inst = inst.borrow();
+                                let methods = &inst.class.borrow().methods;
+                                (
+                                    methods.get(&setter_name(&name)).cloned(),
+                                    methods.get(&*name).is_some_and(|m| m.function.getter),
+                                )
+                            };
+                            if let Some(setter) = setter {
+                                let receiver = Value::Instance(inst);
+                                self.call_method_sync(receiver, setter, vec![val.clone()])?;
+                            } else if getter {
+                                return self.error(format!(
+                                    "Property '{name}' has a getter but no setter."
+                                ));
+                            } else {
+                                inst.borrow_mut()
+                                    .fields
+                                    .insert(name.to_string(), val.clone());
+                            }
+                        }
+                        Value::Native(LoxType::Data(inst)) => inst
+                            .borrow_mut()
+                            .set(Token::dummy(&*name, TokenType::Identifier), val.to_lox()),
+                        Value::Class(cls) => {
+                            cls.borrow_mut()
+                                .statics
+                                .insert(name.to_string(), val.clone());
+                        }
+                        _ => return self.error("Only instances have properties!"),
+                    }
+                    self.stack.push(val);
+                }
+                OpCode::BuildList(len) => {
+                    let start = self.stack.len() - len as usize;
+                    let elements = self.stack.drain(start..).map(|val| val.to_lox()).collect();
+                    self.stack
+                        .push(Value::Native(LoxType::List(gc::alloc(elements))));
+                }
+                OpCode::BuildMap(len) => {
+                    let start = self.stack.len() - 2 * len as usize;
+                    let mut map = MapStore::default();
+                    let mut entries = self.stack.split_off(start).into_iter();
+                    while let (Some(key), Some(val)) = (entries.next(), entries.next()) {
+                        if let Err(msg) = map.insert(key.to_lox(), val.to_lox()) {
+                            return self.error(msg);
+                        }
+                    }
+                    self.stack.push(Value::Native(LoxType::Map(gc::alloc(map))));
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let obj = self.pop();
+                    match get_index(&obj.to_lox(), &index.to_lox()) {
+                        Ok(val) => self.stack.push(val.into()),
+                        Err(err) => return self.error(err.message()),
+                    }
+                }
+                OpCode::SetIndex => {
+                    let val = self.pop();
+                    let index = self.pop();
+                    let obj = self.pop();
+                    if let Err(err) = set_index(&obj.to_lox(), index.to_lox(), val.to_lox()) {
+                        return self.error(err.message());
+                    }
+                    self.stack.push(val);
+                }
+                OpCode::GetSuper(idx) => {
+                    let name = self.constant_str(idx);
+                    let superclass = self.pop();
+                    let receiver = self.pop();
+                    let bound = match (superclass, receiver) {
+                        // `super` in a class method
+                        (Value::Class(superclass), receiver @ Value::Class(_)) => {
+                            let member = superclass.borrow().get_static(&name);
+                            match member {
+                                Some(StaticMember::Field(val)) => val,
+                                Some(StaticMember::Method(method)) => {
+                                    Value::BoundMethod(Rc::new(BoundMethod { receiver, method }))
+                                }
+                                None => return self.error(format!("Undefined property '{name}'.")),
+                            }
+                        }
+                        (Value::Class(superclass), receiver) => {
+                            self.bind_method(&superclass, receiver, &name)?
+                        }
+                        (Value::Native(class), Value::Class(_)) => {
+                            match static_member(&class, &name).flatten() {
+                                Some(val) => val.into(),
+                                None => return self.error(format!("Undefined property '{name}'.")),
+                            }
+                        }
+                        (Value::Native(_), Value::Instance(inst)) => {
+                            let native = inst.borrow().native.clone();
+                            match native {
+                                Some(native) => {
+                                    self.get_property(Value::Native(LoxType::Data(native)), &name)?
+                                }
+                                None => return self.error("Superclass must be a class"),
+                            }
+                        }
+                        _ => return self.error("Superclass must be a class"),
+                    };
+                    self.stack.push(bound);
+                }
+                OpCode::Equal => {
+                    let method = match self.peek(1) {
+                        Value::Instance(inst) => {
+                            let class = Rc::clone(&inst.borrow().class);
+                            let methods = &class.borrow().methods;
+                            EQ_METHODS.iter().find_map(|m| methods.get(*m)).cloned()
+                        }
+                        _ => None,
+                    };
+                    if let Some(method) = method {
+                        self.call(method, 1)?;
+                        continue;
+                    }
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(Value::Bool(a == b));
+                }
+                OpCode::Greater if self.overload(TokenType::Greater)? => {}
+                OpCode::Greater => self.compare_op(|ord| ord.is_gt())?,
+                OpCode::GreaterEqual if self.overload(TokenType::GreaterEqual)? => {}
+                OpCode::GreaterEqual => self.compare_op(|ord| ord.is_ge())?,
+                OpCode::Less if self.overload(TokenType::Less)? => {}
+                OpCode::Less => self.compare_op(|ord| ord.is_lt())?,
+                OpCode::LessEqual if self.overload(TokenType::LessEqual)? => {}
+                OpCode::LessEqual => self.compare_op(|ord| ord.is_le())?,
+                OpCode::Subtract if self.overload(TokenType::Minus)? => {}
+                OpCode::Subtract => self.number_op(NumOp::Sub)?,
+                OpCode::Multiply if self.overload(TokenType::Star)? => {}
+                OpCode::Multiply => self.number_op(NumOp::Mul)?,
+                OpCode::Divide if self.overload(TokenType::Slash)? => {}
+                OpCode::Divide => self.number_op(NumOp::Div)?,
+                OpCode::IntDivide => self.number_op(NumOp::IntDiv)?,
+                OpCode::Modulo if self.overload(TokenType::Percent)? => {}
+                OpCode::Modulo => self.number_op(NumOp::Rem)?,
+                OpCode::BitAnd => self.number_op(NumOp::BitAnd)?,
+                OpCode::BitOr => self.number_op(NumOp::BitOr)?,
+                OpCode::BitXor => self.number_op(NumOp::BitXor)?,
+                OpCode::ShiftLeft => self.number_op(NumOp::Shl)?,
+                OpCode::ShiftRight => self.number_op(NumOp::Shr)?,
+                OpCode::Add if self.overload(TokenType::Plus)? => {}
+                OpCode::Add => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let res = match (a, b) {
+                        (Value::Number(a), Value::Number(b)) => match a.binary(NumOp::Add, b) {
+                            Ok(n) => Value::Number(n),
+                            Err(msg) => return self.error(msg),
+                        },
+                        (Value::String(a), Value::String(b)) => {
+                            let mut s = String::with_capacity(a.len() + b.len());
+                            s.push_str(&a);
+                            s.push_str(&b);
+                            Value::String(s.into())
+                        }
+                        (a, b) => {
+                            return self.error(format!(
+                                "Invalid addition. Operands must be 2 strings or 2 numbers. Found: {a}, {b}"
+                            ))
+                        }
+                    };
+                    self.stack.push(res);
+                }
+                OpCode::Not => {
+                    let val = self.pop();
+                    self.stack.push(Value::Bool(self.is_falsey(&val)));
+                }
+                OpCode::Negate if self.call_operator(NEG_METHOD, 0)? => {}
+                OpCode::Negate => match self.pop() {
+                    Value::Number(n) => match n.negate() {
+                        Ok(n) => self.stack.push(Value::Number(n)),
+                        Err(msg) => return self.error(msg),
+                    },
+                    _ => return self.error("Cannot perform negation on non number"),
+                },
+                OpCode::BitNot => match self.pop() {
+                    Value::Number(n) => match n.bit_not() {
+                        Ok(n) => self.stack.push(Value::Number(n)),
+                        Err(msg) => return self.error(msg),
+                    },
+                    _ => return self.error("Bitwise operands must be integers."),
+                },
+                OpCode::Print => {
+                    let val = self.pop();
+                    if let Err(e) = writeln!(self.interpreter.io.stdout, "{val}") {
+                        return self.error(e.to_string());
+                    }
+                }
+                OpCode::Jump(dist) => self.frame().ip += dist as usize,
+                OpCode::JumpIfFalse(dist) => {
+                    if self.is_falsey(self.peek(0)) {
+                        self.frame().ip += dist as usize;
+                    }
+                }
+                OpCode::Loop(dist) => self.frame().ip -= dist as usize,
+                OpCode::Call(argc) => {
+                    let callee = self.peek(argc as usize).clone();
+                    self.call_value(callee, argc as usize)?;
+                }
+                OpCode::Invoke(idx, argc) => {
+                    let name = self.constant_str(idx);
+                    self.invoke(&name, argc as usize)?;
+                }
+                OpCode::Closure(idx) => {
+                    let function = match self.constant(idx) {
+                        Value::Function(f) => Rc::clone(f),
+                        v => panic!("expected a function constant, found {v}"),
+                    };
+                    let (base, enclosing) = {
+                        let frame = self.frame();
+                        (frame.base, Rc::clone(&frame.closure))
+                    };
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|desc| {
+                            if desc.is_local {
+                                self.capture_upvalue(base + desc.index as usize)
+                            } else {
+                                Rc::clone(&enclosing.upvalues[desc.index as usize])
+                            }
+                        })
+                        .collect();
+                    self.stack.push(Value::Closure(Rc::new(Closure {
+                        function,
+                        upvalues,
+                        globals: Rc::clone(&enclosing.globals),
+                    })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("call frame");
+                    self.call_stack_synced = false;
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class(idx) => {
+                    let name = self.constant_str(idx);
+                    self.stack
+                        .push(Value::Class(Rc::new(RefCell::new(Class::new(
+                            name.to_string(),
+                        )))));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(subclass) = self.pop() else {
+                        unreachable!("inherit into a class")
+                    };
+                    let mut subclass = subclass.borrow_mut();
+                    match self.peek(0).clone() {
+                        Value::Class(superclass) => {
+                            subclass.methods.extend(superclass.borrow().methods.clone());
+                            subclass.native = superclass.borrow().native.clone();
+                            subclass.superclass = Some(superclass);
+                        }
+                        Value::Native(LoxType::Callable(class))
+                            if class.borrow().constructor().is_some() =>
+                        {
+                            subclass.native = Some(LoxType::Callable(class));
+                        }
+                        _ => return self.error("Superclass must be a class"),
+                    }
+                }
+                OpCode::Method(idx) => {
+                    let name = self.constant_str(idx);
+                    let method = self.pop();
+                    if let (Value::Class(cls), Value::Closure(method)) = (self.peek(0), method) {
+                        cls.borrow_mut().methods.insert(name.to_string(), method);
+                    }
+                }
+                OpCode::ClassMethod(idx) => {
+                    let name = self.constant_str(idx);
+                    let method = self.pop();
+                    if let (Value::Class(cls), Value::Closure(method)) = (self.peek(0), method) {
+                        cls.borrow_mut()
+                            .class_methods
+                            .insert(name.to_string(), method);
+                    }
+                }
+                OpCode::Throw => {
+                    let value = self.pop().to_lox();
+                    let mut err = VmError::at(uncaught_message(&value), self.span());
+                    err.trace = self.trace();
+                    err.interrupt_kind = InterruptKind::Throw(value);
+                    return Err(err);
+                }
+                OpCode::PushHandler(dist) => {
+                    let frame = self.frames.last().expect("call frame");
+                    self.handlers.push(Handler {
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                        ip: frame.ip + dist as usize,
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Catch => {
+                    let Value::Error(err) = self.pop() else {
+                        unreachable!("catch without an error")
+                    };
+                    let val = match &err.interrupt_kind {
+                        InterruptKind::Throw(value) => value.clone().into(),
+                        _ => {
+                            let message = err.message.clone();
+                            self.interpreter
+                                .error_instance(message, err.line, &err.trace)
+                                .into()
+                        }
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::Rethrow => {
+                    let Value::Error(err) = self.pop() else {
+                        unreachable!("rethrow without an error")
+                    };
+                    return Err(Rc::unwrap_or_clone(err));
+                }
+                OpCode::Import(idx) => {
+                    let path = self.constant_str(idx);
+                    let module = self.import(&path)?;
+                    self.stack.push(Value::Module(module));
+                }
+            }
+        }
+    }
+
+    /// Call the method the instance on the left of a binary operator defines
+    /// for it, returning whether there was one.
+    fn overload(&mut self, op: TokenType) -> VmResult<bool> {
+        match operator_method(op) {
+            Some(method) => self.call_operator(method, 1),
+            None => Ok(false),
+        }
+    }
+
+    /// Call `method` on the instance `argc` slots below the top of the
+    /// stack, if its class has one, with the values above it as arguments.
+    fn call_operator(&mut self, method: &str, argc: usize) -> VmResult<bool> {
+        let method = match self.peek(argc) {
+            Value::Instance(inst) => inst.borrow().class.borrow().methods.get(method).cloned(),
+            _ => None,
+        };
+        match method {
+            Some(method) => self.call(method, argc).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn number_op(&mut self, op: NumOp) -> VmResult<()> {
+        let b = self.pop();
+        let a = self.pop();
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => match a.binary(op, b) {
+                Ok(n) => {
+                    self.stack.push(Value::Number(n));
+                    Ok(())
+                }
+                Err(msg) => self.error(msg),
+            },
+            _ => self.error("Invalid operands for binary operation"),
+        }
+    }
+
+    fn compare_op(&mut self, test: fn(Ordering) -> bool) -> VmResult<()> {
+        let b = self.pop();
+        let a = self.pop();
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.push(Value::Bool(a.compare(b).is_some_and(test)));
+                Ok(())
+            }
+            _ => self.error("Invalid operands for binary operation"),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, argc: usize) -> VmResult<()> {
+        if argc != closure.function.arity {
+            return self.error(format!(
+                "Expected {} args, got {}",
+                closure.function.arity, argc
+            ));
+        }
+        if self.frames.len() >= FRAMES_MAX || self.interpreter.stack_exhausted() {
+            return self.error("Stack overflow.");
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - argc - 1,
+        });
+        self.call_stack_synced = false;
+        Ok(())
+    }
+
+    fn call_value(&mut self, callee: Value, argc: usize) -> VmResult<()> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, argc),
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), argc)
+            }
+            Value::Class(cls) => {
+                let native_class = match &cls.borrow().native {
+                    Some(LoxType::Callable(class)) => class.borrow().constructor().cloned(),
+                    _ => None,
+                };
+                let native = native_class
+                    .clone()
+                    .map(|class| gc::alloc(LoxInstance::new(class)));
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = Value::Instance(Rc::new(RefCell::new(Instance {
+                    class: Rc::clone(&cls),
+                    fields: Default::default(),
+                    native: native.clone(),
+                })));
+                let init = cls.borrow().methods.get("init").cloned();
+                if let Some(init) = init {
+                    return self.call(init, argc);
+                }
+                // without an `init` of its own, the native superclass sets the instance up
+                let native_init = native_class.and_then(|class| class.find_native_method("init"));
+                match (native, native_init) {
+                    (Some(native), Some(init)) => {
+                        if init.arity != argc {
+                            return self.error(format!("Expected {} args, got {argc}", init.arity));
+                        }
+                        let start = self.stack.len() - argc;
+                        let args = self.stack.drain(start..).map(|arg| arg.to_lox()).collect();
+                        let name = cls.borrow().name.clone();
+                        self.call_native(Some(name), |interpreter| {
+                            (init.fun)(interpreter, &LoxType::Data(native), args)
+                        })?;
+                        Ok(())
+                    }
+                    _ if argc != 0 => self.error(format!("Expected 0 args, got {argc}")),
+                    _ => Ok(()),
+                }
+            }
+            Value::Native(LoxType::Callable(callable)) => {
+                let arity = callable.borrow().arity();
+                if arity != argc {
+                    return self.error(format!("Expected {arity} args, got {argc}"));
+                }
+                let start = self.stack.len() - argc;
+                let args = self.stack.drain(start..).map(|arg| arg.to_lox()).collect();
+                self.pop();
+                let callable = callable.borrow();
+                let class = match callable.kind() {
+                    LoxCallableType::Class => Some(callable.name()),
+                    _ => None,
+                };
+                let val =
+                    self.call_native(class, |interpreter| callable.call(interpreter, args))?;
+                self.stack.push(val.into());
+                Ok(())
+            }
+            _ => self.error("Cannot call uncallable"),
+        }
+    }
+
+    /// The active calls the way [`Interpreter::call_stack`] lists them:
+    /// outermost first, each with the line it was called from.
+    fn call_stack(&self) -> Vec<StackFrame> {
+        self.frames
+            .windows(2)
+            .filter(|pair| !pair[1].closure.function.top_level)
+            .map(|pair| {
+                let caller = &pair[0];
+                let line = caller.closure.function.chunk.spans[caller.ip.saturating_sub(1)].line;
+                StackFrame::new(pair[1].closure.function.name.clone(), line)
+            })
+            .collect()
+    }
+
+    /// Run native code with the interpreter's call stack mirroring the VM's,
+    /// plus a frame for `class` when it constructs an instance of it, which
+    /// is how the tree-walker calls it. Natives such as `Error` read the
+    /// call stack, and its errors are raised at the current instruction.
+    fn call_native<T>(
+        &mut self,
+        class: Option<String>,
+        call: impl FnOnce(&mut Interpreter) -> RuntimeResult<T>,
+    ) -> VmResult<T> {
+        if !self.call_stack_synced {
+            self.interpreter.call_stack = self.call_stack();
+            self.call_stack_synced = true;
+        }
+        let constructing = class.is_some();
+        if let Some(class) = class {
+            let line = self.span().line;
+            self.interpreter
+                .call_stack
+                .push(StackFrame::new(class, line));
+        }
+        let res = call(&mut self.interpreter).map_err(|e| {
+            let e = e.or_at(self.span());
+            let mut err = VmError::at(e.message, e.span);
+            err.trace = match e.interrupt_kind {
+                InterruptKind::Builtin if e.trace.is_empty() => {
+                    stack_trace(&self.interpreter.call_stack, e.line)
+                }
+                _ => e.trace,
+            };
+            err.interrupt_kind = e.interrupt_kind;
+            err
+        });
+        if constructing {
+            self.interpreter.call_stack.pop();
+        }
+        res
+    }
+
+    fn invoke(&mut self, name: &str, argc: usize) -> VmResult<()> {
+        let receiver = self.peek(argc).clone();
+        match &receiver {
+            Value::Instance(inst) => {
+                let field = inst.borrow().fields.get(name).cloned();
+                if let Some(field) = field {
+                    let slot = self.stack.len() - argc - 1;
+                    self.stack[slot] = field.clone();
+                    return self.call_value(field, argc);
+                }
+                let method = inst.borrow().class.borrow().methods.get(name).cloned();
+                match method {
+                    Some(getter) if getter.function.getter => {
+                        let callee = self.call_method_sync(receiver, getter, Vec::new())?;
+                        let slot = self.stack.len() - argc - 1;
+                        self.stack[slot] = callee.clone();
+                        self.call_value(callee, argc)
+                    }
+                    Some(method) => self.call(method, argc),
+                    None => {
+                        let callee = self.get_property(receiver, name)?;
+                        let slot = self.stack.len() - argc - 1;
+                        self.stack[slot] = callee.clone();
+                        self.call_value(callee, argc)
+                    }
+                }
+            }
+            _ => {
+                let callee = self.get_property(receiver, name)?;
+                let slot = self.stack.len() - argc - 1;
+                self.stack[slot] = callee.clone();
+                self.call_value(callee, argc)
+            }
+        }
+    }
+
+    fn get_property(&mut self, obj: Value, name: &str) -> VmResult<Value> {
+        match obj {
+            Value::Instance(inst) => {
+                if let Some(val) = inst.borrow().fields.get(name) {
+                    return Ok(val.clone());
+                }
+                let cls = Rc::clone(&inst.borrow().class);
+                self.bind_method(&cls, Value::Instance(inst), name)
+            }
+            Value::Native(LoxType::Data(inst)) => {
+                let res = inst
+                    .borrow()
+                    .get(&Token::dummy(name, TokenType::Identifier));
+                match res {
+                    Ok(LoxInstanceValue::Free(val)) => Ok(val.into()),
+                    Ok(LoxInstanceValue::Bound(fun)) => {
+                        let bound = fun.borrow().bind(LoxType::Data(Rc::clone(&inst)));
+                        Ok(Value::Native(bound.into()))
+                    }
+                    Ok(LoxInstanceValue::Native(method)) => {
+                        let bound = method.bind(LoxType::Data(Rc::clone(&inst)));
+                        Ok(Value::Native(bound.into()))
+                    }
+                    Err(e) => self.error(e.message),
+                }
+            }
+            Value::String(s) => {
+                match lox_string::get_method(&s, &Token::dummy(name, TokenType::Identifier)) {
+                    Ok(method) => Ok(Value::Native(method)),
+                    Err(e) => self.error(e.message),
+                }
+            }
+            Value::Native(LoxType::List(list)) => {
+                match lox_list::get_method(&list, &Token::dummy(name, TokenType::Identifier)) {
+                    Ok(method) => Ok(Value::Native(method)),
+                    Err(e) => self.error(e.message),
+                }
+            }
+            Value::Native(LoxType::Map(map)) => {
+                match lox_map::get_method(&map, &Token::dummy(name, TokenType::Identifier)) {
+                    Ok(method) => Ok(Value::Native(method)),
+                    Err(e) => self.error(e.message),
+                }
+            }
+            Value::Native(LoxType::Module(module)) => {
+                let val = module
+                    .globals
+                    .borrow()
+                    .get(&Token::dummy(name, TokenType::Identifier));
+                match val {
+                    Ok(val) => Ok(val.into()),
+                    Err(_) => {
+                        self.error(format!("Module '{}' has no member '{name}'.", module.name))
+                    }
+                }
+            }
+            Value::Class(cls) => {
+                let member = cls.borrow().get_static(name);
+                match member {
+                    Some(StaticMember::Field(val)) => Ok(val),
+                    Some(StaticMember::Method(method)) => {
+                        Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                            receiver: Value::Class(cls),
+                            method,
+                        })))
+                    }
+                    None => self.error(format!("Undefined property '{name}'.")),
+                }
+            }
+            Value::Module(module) => match module.globals.borrow().get(name) {
+                Some(val) => Ok(val.clone()),
+                None => self.error(format!("Module '{}' has no member '{name}'.", module.name)),
+            },
+            Value::Native(class @ LoxType::Callable(_)) => match static_member(&class, name) {
+                Some(Some(val)) => Ok(val.into()),
+                Some(None) => self.error(format!("Undefined property '{name}'.")),
+                None => self.error("Only instances have properties!"),
+            },
+            _ => self.error("Only instances have properties!"),
+        }
+    }
+
+    /// Load the module `name` refers to, running its top level in globals of
+    /// its own the first time it is imported.
+    fn import(&mut self, name: &str) -> VmResult<Rc<Module>> {
+        let file = match self.interpreter.modules.find(name) {
+            Ok(file) => file,
+            Err(msg) => return self.error(msg),
+        };
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(Rc::clone(module));
+        }
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(e) => return self.error(format!("Could not read module \"{name}\": {e}")),
+        };
+        let id = match self.interpreter.modules.begin(file.clone()) {
+            Ok(id) => id,
+            Err(msg) => return self.error(msg),
+        };
+        let result = self.run_module(name, &file, source, id);
+        self.interpreter.modules.finish(None);
+        let module = result?;
+        self.modules.insert(file, Rc::clone(&module));
+        Ok(module)
+    }
+
+    fn run_module(
+        &mut self,
+        name: &str,
+        file: &Path,
+        source: String,
+        id: u32,
+    ) -> VmResult<Rc<Module>> {
+        let function = parse_program(source, id).and_then(|stmts| {
+            Compiler::new()
+                .compile(&stmts)
+                .map(|(function, _)| function)
+                .map_err(|e| vec![Diagnostic::from(e)])
+        });
+        let function = match function {
+            Ok(function) => function,
+            Err(errs) => return self.error(load_error(name, file, errs)),
+        };
+        let module = Rc::new(Module {
+            name: module_name(file),
+            globals: Default::default(),
+        });
+        let closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: Vec::new(),
+            globals: Rc::clone(&module.globals),
+        });
+        let depth = self.frames.len();
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        self.call(closure, 0)?;
+        self.run(depth)?;
+        Ok(module)
+    }
+
+    /// Bind the method `name` of `cls` to `receiver`, or run it right away
+    /// if it is a getter.
+    fn bind_method(
+        &mut self,
+        cls: &Rc<RefCell<Class>>,
+        receiver: Value,
+        name: &str,
+    ) -> VmResult<Value> {
+        let method = cls.borrow().methods.get(name).cloned();
+        match method {
+            Some(getter) if getter.function.getter => {
+                self.call_method_sync(receiver, getter, Vec::new())
+            }
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                receiver,
+                method,
+            }))),
+            None => {
+                // then the methods and fields of a native superclass
+                let native = match &receiver {
+                    Value::Instance(inst) => inst.borrow().native.clone(),
+                    _ => None,
+                };
+                match native {
+                    Some(native) => self.get_property(Value::Native(LoxType::Data(native)), name),
+                    None => self.error(format!("Undefined property '{name}'.")),
+                }
+            }
+        }
+    }
+
+    /// Call `method` on `receiver` and run it to completion, for calls the
+    /// VM makes on its own, such as running a getter.
+    fn call_method_sync(
+        &mut self,
+        receiver: Value,
+        method: Rc<Closure>,
+        args: Vec<Value>,
+    ) -> VmResult<Value> {
+        let depth = self.frames.len();
+        let argc = args.len();
+        self.stack.push(receiver);
+        self.stack.extend(args);
+        self.call(method, argc)?;
+        self.run(depth)
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|up| matches!(*up.borrow(), Upvalue::Open(idx) if idx == slot));
+        if let Some(up) = existing {
+            return Rc::clone(up);
+        }
+        let up = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&up));
+        up
+    }
+
+    /// Move every upvalue pointing at `from` or above off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|up| {
+            let mut up = up.borrow_mut();
+            match *up {
+                Upvalue::Open(idx) if idx >= from => {
+                    *up = Upvalue::Closed(stack[idx].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+}
@@ -0,0 +1,272 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    errors::VmError,
+    lox_class::{static_member, LoxInstance},
+    number::Number,
+    tokens::token::LoxType,
+};
+
+use super::chunk::Chunk;
+
+/// Where a closure finds a captured variable when it is created: either a
+/// local slot of the enclosing function or one of the enclosing closure's
+/// own upvalues.
+#[derive(Debug, Clone, Copy)]
+pub struct UpvalueDesc {
+    pub index: u16,
+    pub is_local: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Function {
+    pub name: String,
+    /// Whether this is the top level of a script or module, which tracebacks
+    /// leave out.
+    pub top_level: bool,
+    /// Whether this is a getter, which runs when its property is read.
+    pub getter: bool,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueDesc>,
+}
+
+/// A captured variable. It points into the VM stack while the owning frame
+/// is alive and holds the value itself once that frame returns.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// The global variables of the main script or of one module.
+pub type Globals = Rc<RefCell<HashMap<String, Value>>>;
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// Globals of the script or module the function was declared in.
+    pub globals: Globals,
+}
+
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<String, Rc<Closure>>,
+    /// Methods of the class itself, declared with `class name() { }`. They
+    /// are called with the class as `this`.
+    pub class_methods: HashMap<String, Rc<Closure>>,
+    /// Fields of the class itself, such as `Counter.count`.
+    pub statics: HashMap<String, Value>,
+    pub superclass: Option<Rc<RefCell<Class>>>,
+    /// The native class, such as `File`, at the root of the superclasses.
+    pub native: Option<LoxType>,
+}
+
+/// A member of a class itself: one of its fields, or a class method still to
+/// be bound to the class it was looked up on.
+pub enum StaticMember {
+    Field(Value),
+    Method(Rc<Closure>),
+}
+
+impl Class {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            methods: Default::default(),
+            class_methods: Default::default(),
+            statics: Default::default(),
+            superclass: None,
+            native: None,
+        }
+    }
+
+    /// Look up a member of the class itself: its own fields first, then its
+    /// class methods, then those inherited from the superclass.
+    pub fn get_static(&self, name: &str) -> Option<StaticMember> {
+        if let Some(value) = self.statics.get(name) {
+            return Some(StaticMember::Field(value.clone()));
+        }
+        if let Some(method) = self.class_methods.get(name) {
+            return Some(StaticMember::Method(Rc::clone(method)));
+        }
+        match &self.superclass {
+            Some(superclass) => superclass.borrow().get_static(name),
+            None => static_member(self.native.as_ref()?, name)
+                .flatten()
+                .map(|val| StaticMember::Field(val.into())),
+        }
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<RefCell<Class>>,
+    pub fields: HashMap<String, Value>,
+    /// For a class with a native superclass, the instance of that class
+    /// which holds the native state and answers for its methods and fields.
+    pub native: Option<Rc<RefCell<LoxInstance>>>,
+}
+
+/// A module imported by the VM and the globals its top level defined.
+pub struct Module {
+    pub name: String,
+    pub globals: Globals,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+#[derive(Clone, Default)]
+pub enum Value {
+    #[default]
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Class(Rc<RefCell<Class>>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+    Module(Rc<Module>),
+    /// A value owned by the tree-walking runtime, such as a native function,
+    /// the native `File` class or one of its instances.
+    Native(LoxType),
+    /// An error on its way out of a `try` block, held in a hidden slot while
+    /// the `finally` block runs before it is raised again.
+    Error(Rc<VmError>),
+}
+
+impl Value {
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// Convert back into a [`LoxType`] so the value can be handed to native
+    /// code. Functions, classes and instances created by the VM have no
+    /// tree-walker counterpart and travel as a [`LoxType::Vm`] handle.
+    pub fn to_lox(&self) -> LoxType {
+        match self {
+            Value::Nil => LoxType::Nil,
+            Value::Bool(b) => (*b).into(),
+            Value::Number(n) => (*n).into(),
+            Value::String(s) => LoxType::String(s.to_string()),
+            Value::Function(f) => LoxType::Vm(Object::Function(Rc::clone(f))),
+            Value::Closure(c) => LoxType::Vm(Object::Closure(Rc::clone(c))),
+            Value::Class(c) => LoxType::Vm(Object::Class(Rc::clone(c))),
+            Value::Instance(i) => LoxType::Vm(Object::Instance(Rc::clone(i))),
+            Value::BoundMethod(m) => LoxType::Vm(Object::BoundMethod(Rc::clone(m))),
+            Value::Module(m) => LoxType::Vm(Object::Module(Rc::clone(m))),
+            Value::Native(v) => v.clone(),
+            Value::Error(e) => LoxType::String(e.message.clone()),
+        }
+    }
+}
+
+/// A function, class, instance or module of the VM held by native code, for
+/// example as an element of a list.
+#[derive(Clone)]
+pub enum Object {
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Class(Rc<RefCell<Class>>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+    Module(Rc<Module>),
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        Value::from(self.clone()) == Value::from(other.clone())
+    }
+}
+
+impl From<Object> for Value {
+    fn from(value: Object) -> Self {
+        match value {
+            Object::Function(f) => Value::Function(f),
+            Object::Closure(c) => Value::Closure(c),
+            Object::Class(c) => Value::Class(c),
+            Object::Instance(i) => Value::Instance(i),
+            Object::BoundMethod(m) => Value::BoundMethod(m),
+            Object::Module(m) => Value::Module(m),
+        }
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Value::from(self.clone()))
+    }
+}
+
+impl std::fmt::Debug for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundMethod(a), Value::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => a == b,
+            (Value::Error(a), Value::Error(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<LoxType> for Value {
+    fn from(value: LoxType) -> Self {
+        match value {
+            LoxType::String(s) => Value::String(s.into()),
+            LoxType::Float(n) => Value::Number(Number::Float(n)),
+            LoxType::Int(i) => Value::Number(Number::Int(i)),
+            LoxType::True => Value::Bool(true),
+            LoxType::False => Value::Bool(false),
+            LoxType::Nil | LoxType::InternalNoValue => Value::Nil,
+            LoxType::Vm(object) => object.into(),
+            v => Value::Native(v),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Function(fun) => write!(f, "[Function {}]", fun.name),
+            Value::Closure(c) => write!(f, "[Function {}]", c.function.name),
+            Value::Class(c) => write!(f, "[Class {}]", c.borrow().name),
+            Value::Instance(i) => write!(f, "{} {{}}", i.borrow().class.borrow().name),
+            Value::BoundMethod(m) => write!(f, "[Function {}]", m.method.function.name),
+            Value::Module(m) => write!(f, "<module {}>", m.name),
+            Value::Native(v) => write!(f, "{v}"),
+            Value::Error(e) => write!(f, "{}", e.message),
+        }
+    }
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{s:?}"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
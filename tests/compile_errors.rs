@@ -0,0 +1,48 @@
+use lox::{Backend, Lox, LoxError};
+
+/// Line and message of each error compiling `source` reports.
+fn compile_errors(backend: Backend, source: &str) -> Vec<(usize, String)> {
+    let mut lox = Lox::with_backend(backend);
+    match lox.eval(source) {
+        Err(LoxError::Compile(errs)) => errs.into_iter().map(|e| (e.line, e.message)).collect(),
+        res => panic!("expected compile errors, got {res:?}"),
+    }
+}
+
+#[test]
+fn every_resolver_error_is_reported() {
+    let source = "return 2;
+fun f() { var a = 1; var a = 2; }
+{ var b = b; }
+class A < A {}
+print this;";
+    let expected = [
+        (1, "Can't return from top level code"),
+        (2, "Already a variable with this name in this scope."),
+        (3, "Can't read local variable in its own initializer"),
+        (4, "A class cannot inherit itself"),
+        (5, "Cannot use 'this' outside of a class."),
+    ];
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let errs = compile_errors(backend, source);
+        let errs: Vec<_> = errs.iter().map(|(l, m)| (*l, m.as_str())).collect();
+        assert_eq!(errs, expected, "{backend:?}");
+    }
+}
+
+#[test]
+fn parse_and_resolver_errors_are_reported_together() {
+    let source = "print (1;
+return 2;
+print (3;";
+    let errs = compile_errors(Backend::TreeWalker, source);
+    let lines: Vec<_> = errs.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, [1, 2, 3]);
+}
+
+#[test]
+fn nothing_runs_when_there_are_errors() {
+    let mut lox = Lox::new();
+    assert!(lox.eval("var ran = true; return 1;").is_err());
+    assert_eq!(lox.get_global("ran"), None);
+}
@@ -0,0 +1,54 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+/// Every error `source` reports, rendered against it.
+fn render(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    let errs = match lox.eval(source) {
+        Err(LoxError::Compile(errs)) => errs,
+        Err(LoxError::Runtime(err)) => vec![err],
+        Ok(value) => panic!("expected an error, got {value}"),
+    };
+    let errs: Vec<_> = errs.iter().map(|err| err.render(source)).collect();
+    errs.join("\n")
+}
+
+#[test]
+fn parse_errors_point_at_the_token() {
+    for backend in BACKENDS {
+        assert_eq!(
+            render(backend, "var a = 1;\nprint (a;"),
+            "[line 2] Error: at ';': Expected ')' after expression
+  |
+2 | print (a;
+  |         ^"
+        );
+    }
+}
+
+#[test]
+fn runtime_errors_underline_the_expression() {
+    for backend in BACKENDS {
+        assert_eq!(
+            render(backend, "var s = \"a\";\nprint s + 1;"),
+            "[line 2] Error: Invalid addition. Operands must be 2 strings or 2 numbers. Found: a, 1
+  |
+2 | print s + 1;
+  |       ^^^^^"
+        );
+    }
+}
+
+#[test]
+fn carets_count_characters() {
+    for backend in BACKENDS {
+        assert_eq!(
+            render(backend, "var s = \"é\"; s.len(1);"),
+            "[line 1] Error: Expected 0 args, got 1
+  |
+1 | var s = \"é\"; s.len(1);
+  |              ^^^^^^^^"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use lox::{Backend, Lox};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn nil_equals_only_nil() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, "[nil == nil, nil == false, nil != 0];"),
+            "[true, false, true]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn objects_compare_by_identity() {
+    let source = "fun f() {}
+         class C {}
+         var c = C();
+         var xs = [1];
+         [f == f, C == C, c == c, C() == C(), xs == xs, [1] == [1], {} == {}];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[true, true, true, false, true, false, false]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn classes_define_equality() {
+    let source = "class P {
+             init(x) { this.x = x; }
+             __eq__(other) { return this.x == other.x; }
+         }
+         class Q < P {}
+         class R {
+             init(x) { this.x = x; }
+             equals(other) { return this.x == other.x; }
+         }
+         [P(1) == P(1), P(1) != P(2), Q(1) == Q(1), Q(1) != Q(1), R(2) == R(2)];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[true, true, true, false, true]",
+            "{backend:?}"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use lox::{Lox, LoxError};
+
+fn eval(source: &str) -> String {
+    Lox::new().eval(source).expect("script runs").to_string()
+}
+
+/// The message of the error scanning `source` reports.
+fn scan_error(source: &str) -> String {
+    match Lox::new().eval(source) {
+        Err(LoxError::Compile(errs)) => errs[0].message.clone(),
+        res => panic!("expected a scan error, got {res:?}"),
+    }
+}
+
+#[test]
+fn simple_escapes() {
+    assert_eq!(eval(r#""a\tb\\c\"d\$e\n";"#), "a\tb\\c\"d$e\n");
+    assert_eq!(eval(r#""\r\0".len();"#), "2");
+}
+
+#[test]
+fn unicode_escapes() {
+    assert_eq!(eval(r#""\u{41}\u{e9}\u{1F600}";"#), "Aé😀");
+    assert_eq!(eval(r#""\u{1F600}".len();"#), "1");
+}
+
+#[test]
+fn interpolation_next_to_escapes() {
+    assert_eq!(eval(r#"var n = 2; "\${n} is ${n}\n";"#), "${n} is 2\n");
+}
+
+#[test]
+fn bad_escapes_are_scan_errors() {
+    assert_eq!(scan_error(r#""a\qb";"#), "Unknown escape sequence \\q");
+    assert_eq!(scan_error(r#""\u41";"#), "Expected '{' after \\u");
+    assert_eq!(
+        scan_error(r#""\u{41";"#),
+        "Unicode escapes must look like \\u{1F600}"
+    );
+    assert_eq!(
+        scan_error(r#""\u{}";"#),
+        "Unicode escapes must look like \\u{1F600}"
+    );
+    assert_eq!(
+        scan_error(r#""\u{110000}";"#),
+        "Invalid unicode code point 110000"
+    );
+    assert_eq!(
+        scan_error(r#""\u{D800}";"#),
+        "Invalid unicode code point D800"
+    );
+}
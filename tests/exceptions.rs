@@ -0,0 +1,104 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn try_catch_finally_run_in_order() {
+    let source = "var log = [];
+         fun f() {
+             try { log.push(\"try\"); throw \"x\"; log.push(\"unreachable\"); }
+             catch (e) { log.push(\"catch \" + e); }
+             finally { log.push(\"finally\"); }
+             log.push(\"after\");
+         }
+         f();
+         log;";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[\"try\", \"catch x\", \"finally\", \"after\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn finally_can_override_the_result() {
+    let source = "var log = [];
+         fun f() {
+             try { return \"try\"; }
+             finally { log.push(\"finally\"); return \"finally\"; }
+         }
+         fun g() {
+             try { throw \"a\"; }
+             catch (e) { return \"catch\"; }
+             finally { return \"finally\"; }
+         }
+         fun h() { try { throw \"a\"; } finally { throw \"b\"; } }
+         var thrown;
+         try { h(); } catch (e) { thrown = e; }
+         \"${f()} ${g()} ${thrown} ${log.len()}\";";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "finally finally b 1", "{backend:?}");
+    }
+}
+
+#[test]
+fn finally_runs_when_leaving_a_loop() {
+    let source = "var log = [];
+         for (var i = 0; i < 3; i = i + 1) {
+             try { if (i == 1) break; } finally { log.push(i); }
+         }
+         log;";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[0, 1]", "{backend:?}");
+    }
+}
+
+#[test]
+fn runtime_errors_are_caught_as_error_instances() {
+    let source = "var caught;
+         try {
+             nil.x;
+         } catch (e) { caught = e; }
+         \"${caught.message} ${caught.line}\";";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "Only instances have properties! 3",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn nested_try_passes_errors_outwards() {
+    let source = "var log = [];
+         try {
+             try { throw Error(\"inner\"); } finally { log.push(\"inner finally\"); }
+         } catch (e) { log.push(e.message); }
+         log;";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[\"inner finally\", \"inner\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn uncaught_throws_are_runtime_errors() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        match lox.eval("throw Error(\"boom\");") {
+            Err(LoxError::Runtime(err)) => assert_eq!(err.message, "Uncaught Error: boom"),
+            res => panic!("expected an uncaught error, got {res:?}"),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use lox::{Backend, Lox};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+/// A path in the temp directory for `name`, as a Lox string literal.
+fn temp_path(name: &str, backend: Backend) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!(
+        "lox-file-{}-{name}-{backend:?}.txt",
+        std::process::id()
+    ));
+    format!("{:?}", path.display().to_string())
+}
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn read_lines_seek_and_tell() {
+    for backend in BACKENDS {
+        let path = temp_path("lines", backend);
+        let source = format!(
+            "var f = File({path}, \"w+\");
+             f.write(\"one\\ntwo\\nthree\\n\");
+             f.seek(0);
+             var first = f.readline();
+             var result = [first, f.tell(), f.lines(), f.readline(), f.mode, File.size({path})];
+             f.close();
+             File.remove({path});
+             result;"
+        );
+        assert_eq!(
+            eval(backend, &source),
+            "[\"one\", 4, [\"two\", \"three\"], nil, \"w+\", 14]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn binary_files_hold_bytes() {
+    for backend in BACKENDS {
+        let path = temp_path("binary", backend);
+        let source = format!(
+            "var f = File({path}, \"wb\");
+             f.write([104, 105]);
+             f.close();
+             f = File({path}, \"rb\");
+             var bytes = f.read();
+             var error;
+             try {{ f.readline(); }} catch (e) {{ error = e.message; }}
+             f.close();
+             File.remove({path});
+             [bytes, error, File.exists({path})];"
+        );
+        assert_eq!(
+            eval(backend, &source),
+            "[[104, 105], \"readline() is not available in binary mode.\", false]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn closed_files_refuse_io() {
+    for backend in BACKENDS {
+        let path = temp_path("closed", backend);
+        let source = format!(
+            "var f = File({path}, \"w\");
+             f.close();
+             var errors = [];
+             try {{ f.read(); }} catch (e) {{ errors.push(e.message); }}
+             try {{ f.write(\"x\"); }} catch (e) {{ errors.push(e.message); }}
+             try {{ f.seek(0); }} catch (e) {{ errors.push(e.message); }}
+             f.close();
+             File.remove({path});
+             errors;"
+        );
+        assert_eq!(
+            eval(backend, &source),
+            "[\"File is closed.\", \"File is closed.\", \"File is closed.\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn bad_modes_are_errors() {
+    let source = "var error;
+         try { File(\"x\", \"q\"); } catch (e) { error = e.message; }
+         error;";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "Invalid file mode \"q\". Expected r, w, a, r+, w+ or a+, optionally with b.",
+            "{backend:?}"
+        );
+    }
+}
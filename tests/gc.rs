@@ -0,0 +1,106 @@
+use lox::{Lox, LoxType};
+
+/// Run `script`, then collect and return how many objects were freed.
+fn collected_after(script: &str) -> i64 {
+    let mut lox = Lox::new();
+    lox.eval(script).expect("script runs");
+    match lox.eval("gc()[\"collected\"];") {
+        Ok(LoxType::Int(n)) => n,
+        other => panic!("unexpected gc() result {other:?}"),
+    }
+}
+
+#[test]
+fn collects_closure_cycles() {
+    let collected = collected_after(
+        "fun mk() { var f; fun g() { return f; } f = g; }
+         for (var i = 0; i < 100; i = i + 1) mk();",
+    );
+    assert!(collected >= 100, "collected {collected}");
+}
+
+#[test]
+fn collects_bound_method_cycles() {
+    let collected = collected_after(
+        "class A { init() { this.cb = this.m; } m() {} }
+         for (var i = 0; i < 50; i = i + 1) A();",
+    );
+    assert!(collected >= 50, "collected {collected}");
+}
+
+#[test]
+fn collects_instance_cycles() {
+    let collected = collected_after(
+        "class Node {}
+         fun mk() { var a = Node(); var b = Node(); a.next = b; b.next = a; }
+         for (var i = 0; i < 50; i = i + 1) mk();",
+    );
+    assert!(collected >= 100, "collected {collected}");
+}
+
+#[test]
+fn collects_list_cycles() {
+    let collected = collected_after(
+        "fun mk() { var xs = []; xs.push(xs); }
+         for (var i = 0; i < 50; i = i + 1) mk();",
+    );
+    assert!(collected >= 50, "collected {collected}");
+}
+
+#[test]
+fn keeps_reachable_cycles() {
+    let mut lox = Lox::new();
+    let value = lox
+        .eval(
+            "class Node {}
+             var a = Node(); a.self = a; a.name = \"alive\";
+             fun mk() { var f; fun g() { return \"closure\"; } f = g; return f; }
+             var g = mk();
+             gc();
+             a.self.name + \" \" + g();",
+        )
+        .expect("script runs");
+    assert_eq!(value.to_string(), "alive closure");
+}
+
+#[test]
+fn collects_in_loops_without_calls() {
+    let mut lox = Lox::new();
+    let value = lox
+        .eval(
+            "class N {}
+             var i = 0;
+             while (i < 30000) { var a = N(); a.self = a; i = i + 1; }
+             gc()[\"collections\"];",
+        )
+        .expect("script runs");
+    // the explicit `gc()` is one of them
+    assert!(matches!(value, LoxType::Int(n) if n > 1), "got {value:?}");
+}
+
+#[test]
+fn keeps_objects_referenced_from_class_fields() {
+    let mut lox = Lox::new();
+    let value = lox
+        .eval(
+            "fun mk() {
+               class K {}
+               var y = K(); var x = K(); x.name = \"alive\";
+               K.inst = x; K.other = y;
+               return x;
+             }
+             fun show(o, s) { return o.name; }
+             show(mk(), gc());",
+        )
+        .expect("script runs");
+    assert_eq!(value.to_string(), "alive");
+}
+
+#[test]
+fn collects_class_field_cycles() {
+    let collected = collected_after(
+        "fun mk() { class K {} K.me = K; K.inst = K(); }
+         for (var i = 0; i < 50; i = i + 1) mk();",
+    );
+    assert!(collected >= 50, "collected {collected}");
+}
@@ -0,0 +1,82 @@
+use std::io::Cursor;
+
+use lox::{Backend, Lox, LoxError, SharedBuffer};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn format(backend: Backend, format: &str, args: &str) -> Result<String, String> {
+    let mut lox = Lox::with_backend(backend);
+    match lox.eval(format!("io.format({format:?}, {args});")) {
+        Ok(value) => Ok(value.to_string()),
+        Err(LoxError::Runtime(err)) => Err(err.message),
+        Err(err) => panic!("{err}"),
+    }
+}
+
+#[test]
+fn printf_widths_and_flags() {
+    for backend in BACKENDS {
+        assert_eq!(
+            format(
+                backend,
+                "[%5d|%-5d|%05d|%x|%X|%%]",
+                "[42, 42, -42, 255, 255]"
+            ),
+            Ok("[   42|42   |-0042|ff|FF|%]".into())
+        );
+        assert_eq!(
+            format(backend, "%.2f|%8.3f|%05.1f", "[3.14159, 2.5, -2.5]"),
+            Ok("3.14|   2.500|-02.5".into())
+        );
+        assert_eq!(
+            format(backend, "%5s|%-5s|%s", "[\"ab\", \"cd\", nil]"),
+            Ok("   ab|cd   |nil".into())
+        );
+    }
+}
+
+#[test]
+fn format_arguments_must_match() {
+    for backend in BACKENDS {
+        assert_eq!(
+            format(backend, "%d", "1"),
+            Err("Format arguments must be a list, got 1.".into())
+        );
+        assert_eq!(
+            format(backend, "%d %d", "[1]"),
+            Err("Not enough arguments for the format.".into())
+        );
+        assert_eq!(
+            format(backend, "%d", "[1, 2]"),
+            Err("Too many arguments for the format.".into())
+        );
+        assert_eq!(
+            format(backend, "%d", "[1.5]"),
+            Err("%d expects an integer, got 1.5.".into())
+        );
+        assert_eq!(
+            format(backend, "%q", "[1]"),
+            Err("Unknown format specifier '%q'.".into())
+        );
+    }
+}
+
+#[test]
+fn streams_can_be_redirected() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        let (out, err) = (SharedBuffer::new(), SharedBuffer::new());
+        lox.set_output(out.clone());
+        lox.set_error_output(err.clone());
+        lox.set_input(Cursor::new("Ada\nsecond\nrest\nof it"));
+        let source = "var name = io.input(\"name? \");
+             var line = io.readline();
+             io.printf(\"hi %s, %s|\", [name, line]);
+             io.write(io.read_all());
+             io.eprint(\"oops\");
+             io.readline();";
+        assert_eq!(lox.eval(source).unwrap().to_string(), "nil");
+        assert_eq!(out.take(), "name? hi Ada, second|rest\nof it");
+        assert_eq!(err.take(), "oops\n");
+    }
+}
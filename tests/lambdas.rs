@@ -0,0 +1,64 @@
+use lox::{Backend, Lox};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn function_expressions_and_arrows() {
+    let source = "var add = fun (a, b) { return a + b; };
+         var square = (x) => x * x;
+         [add(1, 2), square(5), ((a, b) => a - b)(5, 3), (() => \"none\")(), square];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[3, 25, 2, \"none\", [Function lambda]]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn arrows_with_block_bodies() {
+    let source = "var sign = (x) => {
+             if (x < 0) return -1;
+             return 1;
+         };
+         var nothing = () => {};
+         var map = (k) => ({\"k\": k});
+         [sign(-3), sign(3), nothing(), map(1)[\"k\"]];";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[-1, 1, nil, 1]", "{backend:?}");
+    }
+}
+
+#[test]
+fn lambdas_close_over_their_scope() {
+    let source = "fun counter() {
+             var n = 0;
+             return () => { n = n + 1; return n; };
+         }
+         var c = counter();
+         c();
+         class Box {
+             init(x) { this.x = x; }
+             getter() { return () => this.x; }
+         }
+         [c(), Box(7).getter()()];";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[2, 7]", "{backend:?}");
+    }
+}
+
+#[test]
+fn lambdas_are_values() {
+    let source = "var fs = [(a) => a + 1, (a) => a * 2];
+         var m = {\"f\": fun (s) { return s + \"!\"; }};
+         [fs[1](fs[0](2)), m[\"f\"](\"hi\")];";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[6, \"hi!\"]", "{backend:?}");
+    }
+}
@@ -0,0 +1,65 @@
+use lox::{Backend, Lox};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn continue_in_for_runs_the_increment() {
+    let source = "var seen = [];
+         for (var i = 0; i < 5; i = i + 1) {
+             if (i % 2 == 0) continue;
+             seen.push(i);
+         }
+         seen;";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[1, 3]", "{backend:?}");
+    }
+}
+
+#[test]
+fn continue_in_while_rechecks_the_condition() {
+    let source = "var i = 0;
+         var sum = 0;
+         while (i < 5) {
+             i = i + 1;
+             if (i == 3) continue;
+             sum = sum + i;
+         }
+         sum;";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "12", "{backend:?}");
+    }
+}
+
+#[test]
+fn break_leaves_the_innermost_loop() {
+    let source = "var pairs = [];
+         for (var i = 0; i < 3; i = i + 1) {
+             for (var j = 0; j < 3; j = j + 1) {
+                 if (j > i) break;
+                 pairs.push(\"${i}${j}\");
+             }
+         }
+         \" \".join(pairs);";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "00 10 11 20 21 22", "{backend:?}");
+    }
+}
+
+#[test]
+fn continue_closes_over_body_variables() {
+    let source = "var fs = [];
+         for (var i = 0; i < 3; i = i + 1) {
+             var j = i;
+             if (j == 1) continue;
+             fs.push(() => j);
+         }
+         \"${fs[0]()}${fs[1]()}\";";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "02", "{backend:?}");
+    }
+}
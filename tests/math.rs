@@ -0,0 +1,73 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn functions() {
+    let source = "[math.sqrt(16), math.pow(2, 10), math.floor(2.7), math.ceil(2.1),
+          math.abs(-3), math.min(1, 2), math.max(1, 2.5), math.log2(8), math.exp(0)];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[4.0, 1024, 2.0, 3.0, 3, 1, 2.5, 3.0, 1.0]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn rounding_keeps_integers() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, "[math.round(2.5), math.round(3), math.floor(3)];"),
+            "[3.0, 3, 3]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn constants() {
+    let source = "[math.pi, math.is_nan(math.nan), math.is_inf(math.inf), math.is_inf(1)];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[3.141592653589793, true, true, false]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn seeded_random_repeats() {
+    let source = "math.seed(7);
+         var a = [math.random(), math.random()];
+         math.seed(7);
+         var b = [math.random(), math.random()];
+         [a[0] == b[0], a[1] == b[1], a[0] != a[1], a[0] >= 0 and a[0] < 1];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[true, true, true, true]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn non_numbers_are_errors() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        match lox.eval("math.sqrt(\"x\");") {
+            Err(LoxError::Runtime(err)) => {
+                assert_eq!(err.message, "sqrt() expects a number, got \"x\".")
+            }
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+    }
+}
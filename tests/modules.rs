@@ -0,0 +1,72 @@
+use std::{fs, path::PathBuf};
+
+use lox::{Backend, Lox, LoxType};
+
+/// Write `source` to a module file of its own and return its path.
+fn module(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lox-modules-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.lox"));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn host_natives_are_visible(backend: Backend) {
+    let path = module(
+        &format!("host_{backend:?}"),
+        "var doubled = double(answer);",
+    );
+    let mut lox = Lox::with_backend(backend);
+    lox.set_global("answer", LoxType::Int(21));
+    lox.register_fn("double", 1, |args| match &args[0] {
+        LoxType::Int(n) => Ok(LoxType::Int(n * 2)),
+        _ => Err("double() expects an integer".into()),
+    });
+    let script = format!("import {:?} as m; m.doubled;", path.display().to_string());
+    assert_eq!(lox.eval(&script).unwrap(), LoxType::Int(42));
+}
+
+#[test]
+fn modules_see_host_natives() {
+    host_natives_are_visible(Backend::TreeWalker);
+}
+
+#[test]
+fn vm_modules_see_host_natives() {
+    host_natives_are_visible(Backend::Vm);
+}
+
+#[test]
+fn vm_module_functions_use_module_globals() {
+    let path = module(
+        "counter_vm",
+        "var count = 0;
+         fun inc() { count = count + 1; return count; }",
+    );
+    let mut lox = Lox::with_backend(Backend::Vm);
+    let script = format!(
+        "var count = 100;
+         import {:?} as c;
+         c.inc();
+         c.inc() + c.count + count;",
+        path.display().to_string()
+    );
+    assert_eq!(lox.eval(&script).unwrap(), LoxType::Int(104));
+}
+
+#[test]
+fn import_words_are_still_names() {
+    let path = module("shapes", "var area = 6;");
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut lox = Lox::with_backend(backend);
+        let script = format!(
+            "var from = 1;
+             fun import(x) {{ return x; }}
+             from \"{0}\" import area;
+             import \"{0}\" as as;
+             import(from + as.area + area);",
+            path.display()
+        );
+        assert_eq!(lox.eval(&script).unwrap(), LoxType::Int(13), "{backend:?}");
+    }
+}
@@ -0,0 +1,106 @@
+use lox::{
+    errors::{RuntimeError, RuntimeResult},
+    interpreter::Interpreter,
+    Backend, Lox, LoxError, LoxType, NativeClass,
+};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+struct Count(i64);
+
+fn init(_: &mut Interpreter, this: &LoxType, args: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let (LoxType::Data(inst), LoxType::Int(start)) = (this, &args[0]) else {
+        return Err(RuntimeError::new("Counter(start) expects an integer.", 0));
+    };
+    inst.borrow_mut().set_native(Count(*start));
+    Ok(LoxType::Nil)
+}
+
+fn next(_: &mut Interpreter, this: &LoxType, _: Vec<LoxType>) -> RuntimeResult<LoxType> {
+    let LoxType::Data(inst) = this else {
+        unreachable!("Counter method bound to a non instance")
+    };
+    let count = inst
+        .borrow()
+        .native::<Count>()
+        .ok_or_else(|| RuntimeError::new("Counter was not initialized.", 0))?;
+    count.borrow_mut().0 += 1;
+    let n = count.borrow().0;
+    Ok(LoxType::Int(n))
+}
+
+fn lox(backend: Backend) -> Lox {
+    let mut lox = Lox::with_backend(backend);
+    lox.register_class(
+        NativeClass::new("Counter")
+            .init(1, init)
+            .method("next", 0, next)
+            .static_member("step", LoxType::Int(1)),
+    );
+    lox
+}
+
+#[test]
+fn methods_share_native_state() {
+    let source = "var c = Counter(10);
+         c.next();
+         [c.next(), Counter.step, Counter(0).next()];";
+    for backend in BACKENDS {
+        let value = lox(backend).eval(source).unwrap();
+        assert_eq!(value.to_string(), "[12, 1, 1]", "{backend:?}");
+    }
+}
+
+#[test]
+fn subclasses_call_super_init() {
+    let source = "class Twice < Counter {
+             init(start) { super.init(start * 2); this.label = \"twice\"; }
+             next() { super.next(); return super.next(); }
+         }
+         var t = Twice(5);
+         [t.next(), t.next(), t.label, Twice.step];";
+    for backend in BACKENDS {
+        let value = lox(backend).eval(source).unwrap();
+        assert_eq!(value.to_string(), "[12, 14, \"twice\", 1]", "{backend:?}");
+    }
+}
+
+#[test]
+fn subclasses_inherit_the_native_init() {
+    let source = "class Plain < Counter {}
+         Plain(3).next();";
+    for backend in BACKENDS {
+        let value = lox(backend).eval(source).unwrap();
+        assert_eq!(value.to_string(), "4", "{backend:?}");
+    }
+}
+
+#[test]
+fn skipping_super_init_leaves_no_state() {
+    let source = "class Lazy < Counter { init() {} }
+         Lazy().next();";
+    for backend in BACKENDS {
+        match lox(backend).eval(source) {
+            Err(LoxError::Runtime(err)) => {
+                assert_eq!(err.message, "Counter was not initialized.", "{backend:?}");
+                assert_eq!(err.line, 2, "{backend:?}");
+            }
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+    }
+}
+
+#[test]
+fn native_init_errors_reach_the_script() {
+    let source = "var error;
+         try { Counter(\"a\"); } catch (e) { error = e.message; }
+         error;";
+    for backend in BACKENDS {
+        let value = lox(backend).eval(source).unwrap();
+        assert_eq!(
+            value.to_string(),
+            "Counter(start) expects an integer.",
+            "{backend:?}"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+fn error(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    match lox.eval(source) {
+        Err(LoxError::Runtime(err)) => err.message,
+        Err(LoxError::Compile(errs)) => errs[0].message.clone(),
+        Ok(value) => panic!("expected an error, got {value}"),
+    }
+}
+
+#[test]
+fn integers_and_floats() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(
+                backend,
+                "[1 + 2, 1 + 2.0, 7 / 2, 6 / 2, 0xff, 0b1010, 1_000_000];"
+            ),
+            "[3, 3.0, 3.5, 3.0, 255, 10, 1000000]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn modulo_takes_the_sign_of_the_divisor() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, "[7 % 3, -7 % 3, 7 % -3, -7 % -3, -7.5 % 2];"),
+            "[1, 2, -2, -1, 0.5]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn floor_division() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, "[7 ~/ 2, -7 ~/ 2, 7 ~/ -2, 7.5 ~/ 2];"),
+            "[3, -4, -4, 3]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn bitwise_operators() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, "[5 & 3, 5 | 3, 5 ^ 3, ~5, 1 << 4, -16 >> 2];"),
+            "[1, 7, 6, -6, 16, -4]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn overflow_is_an_error() {
+    for backend in BACKENDS {
+        for source in [
+            "9223372036854775807 + 1;",
+            "-9223372036854775807 - 2;",
+            "4611686018427387904 * 2;",
+            "-(-9223372036854775807 - 1);",
+        ] {
+            assert_eq!(error(backend, source), "Integer overflow.", "{source}");
+        }
+        assert_eq!(error(backend, "1 ~/ 0;"), "Division by zero.");
+        assert_eq!(error(backend, "1 % 0;"), "Division by zero.");
+        assert_eq!(
+            error(backend, "1 << 64;"),
+            "Shift amount must be between 0 and 63."
+        );
+        assert_eq!(
+            error(backend, "99999999999999999999;"),
+            "Integer literal is too large."
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+const VECTOR: &str = "class V {
+    init(x) { this.x = x; }
+    __add__(o) { return V(this.x + o.x); }
+    __sub__(o) { return V(this.x - o.x); }
+    __mul__(k) { return V(this.x * k); }
+    __div__(k) { return V(this.x / k); }
+    __mod__(k) { return V(this.x % k); }
+    __lt__(o) { return this.x < o.x; }
+    __le__(o) { return this.x <= o.x; }
+    __gt__(o) { return this.x > o.x; }
+    __ge__(o) { return this.x >= o.x; }
+    __neg__() { return V(-this.x); }
+}
+";
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    let source = format!("{VECTOR}{source}");
+    lox.eval(source).expect("script runs").to_string()
+}
+
+#[test]
+fn operators_call_special_methods() {
+    let source = "var a = V(6);
+         var b = V(4);
+         [(a + b).x, (a - b).x, (a * 2).x, (a / 4).x, (a % 4).x, (-a).x,
+          a < b, a <= b, a > b, a >= b];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[10, 2, 12, 1.5, 2, -6, false, false, true, true]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn overloads_are_inherited_and_can_be_overridden() {
+    let source = "class W < V {}
+         class Scaled < V {
+             __add__(o) { return super.__add__(o) * 10; }
+         }
+         [(W(1) + W(2)).x, (-W(3)).x, (Scaled(1) + Scaled(2)).x, W(1) < W(2)];";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source), "[3, -3, 30, true]", "{backend:?}");
+    }
+}
+
+#[test]
+fn instances_without_overloads_are_errors() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        match lox.eval("class E {} E() + 1;") {
+            Err(LoxError::Runtime(err)) => assert_eq!(
+                err.message,
+                "Invalid addition. Operands must be 2 strings or 2 numbers. Found: E {}, 1"
+            ),
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+    }
+}
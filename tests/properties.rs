@@ -0,0 +1,54 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+const CIRCLE: &str = "class Circle {
+    init(r) { this.r = r; }
+    area { return this.r * this.r * 3; }
+    diameter { return this.r * 2; }
+    diameter=(d) { this.r = d / 2; }
+}
+";
+
+fn eval(backend: Backend, source: &str) -> Result<String, LoxError> {
+    let mut lox = Lox::with_backend(backend);
+    let value = lox.eval(format!("{CIRCLE}{source}"))?;
+    Ok(value.to_string())
+}
+
+#[test]
+fn getters_and_setters_run_on_access() {
+    let source = "var c = Circle(1);
+         c.diameter = 4;
+         [c.r, c.area, c.diameter];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source).unwrap(),
+            "[2.0, 12.0, 4.0]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn getters_are_inherited_and_reach_super() {
+    let source = "class Big < Circle { area { return super.area * 10; } }
+         var c = Big(2);
+         [c.area, c.diameter];";
+    for backend in BACKENDS {
+        assert_eq!(eval(backend, source).unwrap(), "[120, 4]", "{backend:?}");
+    }
+}
+
+#[test]
+fn assigning_a_getter_without_a_setter_is_an_error() {
+    for backend in BACKENDS {
+        match eval(backend, "Circle(1).area = 1;") {
+            Err(LoxError::Runtime(err)) => {
+                assert_eq!(err.message, "Property 'area' has a getter but no setter.");
+                assert_eq!(err.line, 7);
+            }
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+    }
+}
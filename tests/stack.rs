@@ -0,0 +1,46 @@
+use std::thread;
+
+use lox::{Backend, Lox, LoxError};
+
+fn overflow(backend: Backend, source: &str) {
+    overflow_in(Lox::with_backend(backend), source);
+}
+
+fn overflow_in(mut lox: Lox, source: &str) {
+    match lox.eval(source) {
+        Err(LoxError::Runtime(err)) => assert_eq!(err.message, "Stack overflow."),
+        res => panic!("expected a stack overflow, got {res:?}"),
+    }
+}
+
+#[test]
+fn deep_recursion_fails_instead_of_crashing() {
+    let source = "fun f(n) { return f(n + 1); } f(0);";
+    overflow(Backend::TreeWalker, source);
+    overflow(Backend::Vm, source);
+}
+
+#[test]
+fn recursive_getters_fail_instead_of_crashing() {
+    let source = "class A { x { return this.x; } } A().x;";
+    overflow(Backend::TreeWalker, source);
+    overflow(Backend::Vm, source);
+}
+
+#[test]
+fn stack_size_is_configurable() {
+    const STACK_SIZE: usize = 64 * 1024 * 1024;
+    let source = "fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); } f(500);";
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = Lox::new();
+            lox.set_stack_size(STACK_SIZE);
+            assert_eq!(lox.eval(source).unwrap().to_string(), "500");
+            lox.set_stack_size(0);
+            overflow_in(lox, source);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,76 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn eval(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    lox.eval(source).expect("script runs").to_string()
+}
+
+fn error(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    match lox.eval(source) {
+        Err(LoxError::Runtime(err)) => err.message,
+        res => panic!("expected a runtime error, got {res:?}"),
+    }
+}
+
+#[test]
+fn string_methods() {
+    let source = "[\" abc \".trim(), \"Ab\".upper(), \"Ab\".lower(), \"aXa\".replace(\"a\", \"b\"),
+          \"abc\".starts_with(\"ab\"), \"abc\".ends_with(\"bc\"), \"ab\".repeat(3),
+          \"a,b,c\".split(\",\"), \"-\".join([\"a\", 1])];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[\"abc\", \"AB\", \"ab\", \"bXb\", true, true, \"ababab\", [\"a\", \"b\", \"c\"], \"a-1\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn positions_count_characters() {
+    let source = "[\"héllo\".len(), \"héllo\".substring(1, 3), \"héllo\".substring(3, nil),
+          \"héllo\".find(\"l\"), \"abc\".find(\"z\"), \"é\".code_at(0)];";
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(backend, source),
+            "[5, \"él\", \"lo\", 2, nil, 233]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn conversions() {
+    for backend in BACKENDS {
+        assert_eq!(
+            eval(
+                backend,
+                "[str(1.5), str(nil), num(\"42\"), num(\"2.5\"), chr(65)];"
+            ),
+            "[\"1.5\", \"nil\", 42, 2.5, \"A\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn bad_arguments_are_errors() {
+    for backend in BACKENDS {
+        assert_eq!(
+            error(backend, "num(\"x\");"),
+            "Cannot convert \"x\" to a number."
+        );
+        assert_eq!(
+            error(backend, "\"abc\".substring(2, 1);"),
+            "Substring start 2 is after its end 1."
+        );
+        assert_eq!(
+            error(backend, "\"abc\".code_at(5);"),
+            "String index 5 out of range for length 3."
+        );
+        assert_eq!(error(backend, "chr(-1);"), "Invalid character code -1.");
+    }
+}
@@ -0,0 +1,64 @@
+use lox::{Backend, Lox, LoxError};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+fn runtime_error(backend: Backend, source: &str) -> String {
+    let mut lox = Lox::with_backend(backend);
+    match lox.eval(source) {
+        Err(LoxError::Runtime(err)) => err.to_string(),
+        res => panic!("expected a runtime error, got {res:?}"),
+    }
+}
+
+#[test]
+fn uncaught_errors_list_the_calls_innermost_first() {
+    let source = "fun f(a) {
+  return a + nil;
+}
+fun g() { return f(1); }
+g();";
+    for backend in BACKENDS {
+        assert_eq!(
+            runtime_error(backend, source),
+            "[line 2] Error: Invalid addition. Operands must be 2 strings or 2 numbers. Found: 1, nil
+  at f() line 2
+  at g() line 4"
+        );
+    }
+}
+
+#[test]
+fn repeated_frames_are_collapsed() {
+    let source = "fun f(n) {
+  if (n == 0) return nil + 1;
+  return f(n - 1);
+}
+f(5);";
+    for backend in BACKENDS {
+        assert_eq!(
+            runtime_error(backend, source),
+            "[line 2] Error: Invalid addition. Operands must be 2 strings or 2 numbers. Found: nil, 1
+  at f() line 2
+  at f() line 3
+  [previous frame repeated 4 more times]"
+        );
+    }
+}
+
+#[test]
+fn caught_errors_carry_the_stack() {
+    let source = "fun f() { throw Error(\"boom\"); }
+fun g() { f(); }
+var stack;
+try { g(); } catch (e) { stack = e.stack; }
+stack;";
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        let stack = lox.eval(source).unwrap();
+        assert_eq!(
+            stack.to_string(),
+            "[\"at f() line 1\", \"at g() line 2\"]",
+            "{backend:?}"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use lox::{Backend, Lox};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+const CONDITIONS: &str = "var seen = [];
+     if (nil) seen.push(\"if\");
+     while (nil) { seen.push(\"while\"); break; }
+     seen.push(nil or \"or\");
+     seen.push(nil and \"and\");
+     seen.push(!nil);
+     seen.push(0 and \"zero\");
+     seen.push(\"\" and \"empty\");
+     seen;";
+
+#[test]
+fn nil_and_false_are_falsy() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        assert_eq!(
+            lox.eval(CONDITIONS).unwrap().to_string(),
+            "[\"or\", nil, true, \"zero\", \"empty\"]",
+            "{backend:?}"
+        );
+    }
+}
+
+#[test]
+fn nil_can_be_truthy_again() {
+    for backend in BACKENDS {
+        let mut lox = Lox::with_backend(backend);
+        lox.set_nil_is_truthy(true);
+        assert_eq!(
+            lox.eval(CONDITIONS).unwrap().to_string(),
+            "[\"if\", \"while\", nil, \"and\", false, \"zero\", \"empty\"]",
+            "{backend:?}"
+        );
+    }
+}
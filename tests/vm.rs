@@ -0,0 +1,133 @@
+use lox::{Backend, Lox, LoxType};
+
+fn eval_vm(script: &str) -> LoxType {
+    let mut lox = Lox::with_backend(Backend::Vm);
+    lox.eval(script).expect("script runs")
+}
+
+#[test]
+fn lists_and_indexing() {
+    let value = eval_vm(
+        "var xs = [1, 2, 3];
+         xs[0] = 10;
+         xs.push(4);
+         xs[0] + xs[3] + xs.len();",
+    );
+    assert_eq!(value.to_string(), "18");
+}
+
+#[test]
+fn maps_and_indexing() {
+    let value = eval_vm(
+        "var m = {\"a\": 1, 2: [\"b\"]};
+         m[\"c\"] = m[2][0];
+         m[\"c\"] + \"${m.len()}\";",
+    );
+    assert_eq!(value.to_string(), "b3");
+}
+
+#[test]
+fn lists_hold_vm_objects() {
+    let value = eval_vm(
+        "class P { init(x) { this.x = x; } }
+         fun add(a, b) { return a + b; }
+         var ps = [P(1), P(2)];
+         var m = {\"add\": add};
+         m[\"add\"](ps[0].x, ps[1].x);",
+    );
+    assert_eq!(value.to_string(), "3");
+}
+
+#[test]
+fn eval_returns_handles_for_vm_objects() {
+    let mut lox = Lox::with_backend(Backend::Vm);
+    let value = lox.eval("class C {} var c = C(); c;").unwrap();
+    assert!(matches!(value, LoxType::Vm(_)), "got {value:?}");
+    assert_eq!(value.to_string(), "C {}");
+    assert_eq!(lox.get_global("c"), Some(value.clone()));
+
+    // the handle is the same object once it is back in the VM
+    lox.set_global("d", value);
+    assert_eq!(lox.eval("c == d;").unwrap(), LoxType::True);
+}
+
+#[test]
+fn exceptions() {
+    let value = eval_vm(
+        "var log = [];
+         fun f(n) {
+             try {
+                 if (n == 0) throw \"zero\";
+                 return n / nil;
+             } catch (e) {
+                 log.push(e);
+                 return \"caught\";
+             } finally {
+                 log.push(\"finally\");
+             }
+         }
+         f(0);
+         f(1);
+         for (var i = 0; i < 3; i = i + 1) {
+             try { if (i == 1) continue; log.push(i); } finally { log.push(-i); }
+         }
+         log[2] = log[2].message;
+         log;",
+    );
+    assert_eq!(
+        value.to_string(),
+        "[\"zero\", \"finally\", \"Invalid operands for binary operation\", \"finally\", 0, 0, -1, 2, -2]"
+    );
+}
+
+#[test]
+fn class_methods_and_fields() {
+    let value = eval_vm(
+        "class Shape {
+             class create() { this.made = this.made + 1; return this(); }
+             class kind() { return \"shape\"; }
+         }
+         class Square < Shape {
+             class kind() { return \"square of \" + super.kind(); }
+         }
+         Shape.made = 0;
+         Square.create();
+         Shape.create();
+         \"${Square.kind()} ${Shape.made} ${Square.made}\";",
+    );
+    assert_eq!(value.to_string(), "square of shape 1 1");
+}
+
+#[test]
+fn getters_and_setters() {
+    let value = eval_vm(
+        "class Temp {
+             init() { this.c = 0; }
+             f { return this.c * 9 / 5 + 32; }
+             f=(value) { this.c = (value - 32) * 5 / 9; }
+             k { return this.c + 273; }
+         }
+         var t = Temp();
+         t.f = 212;
+         var error;
+         try { t.k = 0; } catch (e) { error = e.message; }
+         \"${t.c} ${t.f} ${error}\";",
+    );
+    assert_eq!(
+        value.to_string(),
+        "100.0 212.0 Property 'k' has a getter but no setter."
+    );
+}
+
+#[test]
+fn native_superclasses() {
+    let value = eval_vm(
+        "class NotFound < Error {
+             init(path) { super.init(\"no file \" + path); this.path = path; }
+         }
+         var caught;
+         try { throw NotFound(\"a.txt\"); } catch (e) { caught = e; }
+         \"${caught.message} ${caught.path} ${caught.line}\";",
+    );
+    assert_eq!(value.to_string(), "no file a.txt a.txt 5");
+}